@@ -0,0 +1,86 @@
+// This benchmark checks that moving the snake takes the same time however long it
+// is. Every update, the snake asks whether its new head is about to run into its
+// own body, and that question used to mean walking every segment. Now it's a
+// lookup in the snake's `OccupancyGrid`, so a snake covering a tenth of a
+// 1000x1000 board should move just as fast as a short one.
+// Run it with `cargo bench`.
+use std::time::{Duration, Instant};
+
+use ggez_snake::{Ate, Board, BoardView, Direction, Food, FoodField, GridPosition, Snake};
+
+const BOARD_SIZE: (i16, i16) = (1000, 1000);
+// The lengths of snake we time, up to one covering a tenth of the board.
+const SNAKE_LENGTHS: [usize; 3] = [1_000, 10_000, 100_000];
+// How many updates we time for each length.
+const UPDATES: usize = 10_000;
+
+// The snake goes back and forth across the board a row at a time, dropping down a
+// row at either end, so it never runs into itself.
+fn steer(snake: &mut Snake, board: &Board) {
+    let head = snake.head().pos;
+    match snake.dir() {
+        Direction::Right if head.x == board.width - 1 => {
+            snake.turn(Direction::Down);
+            snake.turn(Direction::Left);
+        }
+        Direction::Left if head.x == 0 => {
+            snake.turn(Direction::Down);
+            snake.turn(Direction::Right);
+        }
+        _ => (),
+    }
+}
+
+// Moves the snake once. If `grow` is set, we put food right in front of it first,
+// so that it gets a segment longer.
+fn update(snake: &mut Snake, board: &Board, food: &mut FoodField, grow: bool) {
+    steer(snake, board);
+    let ahead = board.step(snake.head().pos, snake.dir());
+    if let (true, Some(pos)) = (grow, ahead) {
+        food.insert(Food::new(pos));
+    }
+    snake.update(&BoardView::new(board, food));
+    food.remove(snake.head().pos);
+    assert!(snake.ate().is_none_or(|ate| ate != Ate::Itself));
+}
+
+// Grows a snake to `length` segments and then times `UPDATES` more updates.
+fn time_updates(board: &Board, length: usize) -> Duration {
+    let mut food = FoodField::new();
+    let mut snake = Snake::new(GridPosition::new(1, 0), Direction::Right, board);
+    while snake.length() < length {
+        update(&mut snake, board, &mut food, true);
+    }
+    let start = Instant::now();
+    for _ in 0..UPDATES {
+        update(&mut snake, board, &mut food, false);
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(snake.length(), length);
+    elapsed
+}
+
+fn main() {
+    let board = Board::new(BOARD_SIZE.0, BOARD_SIZE.1);
+    println!(
+        "{} updates of a snake on a {}x{} board:",
+        UPDATES, BOARD_SIZE.0, BOARD_SIZE.1
+    );
+    let times: Vec<Duration> = SNAKE_LENGTHS
+        .iter()
+        .map(|&length| {
+            let time = time_updates(&board, length);
+            println!(
+                "  {:>7} segments: {:?} ({:?} per update)",
+                length,
+                time,
+                time / UPDATES as u32
+            );
+            time
+        })
+        .collect();
+    println!(
+        "  the longest snake took {:.1}x as long as the shortest",
+        times[times.len() - 1].as_secs_f64() / times[0].as_secs_f64().max(f64::EPSILON)
+    );
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::food::{Food, FoodField};
+use crate::grid::{Direction, GridPosition};
+
+// What happens when the snake reaches the edge of the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgePolicy {
+    // The snake comes back in on the opposite side.
+    #[default]
+    Wrap,
+    // The edges are walls, and running into one ends the game.
+    Solid,
+    // The edges are walls, but the snake is deflected along them instead of dying.
+    Bounce,
+}
+
+impl fmt::Display for EdgePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EdgePolicy::Wrap => "wrap",
+            EdgePolicy::Solid => "solid",
+            EdgePolicy::Bounce => "bounce",
+        };
+        f.write_str(name)
+    }
+}
+
+// We can also read an `EdgePolicy` from its name, which is handy for command
+// line flags.
+impl FromStr for EdgePolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "wrap" => Ok(EdgePolicy::Wrap),
+            "solid" => Ok(EdgePolicy::Solid),
+            "bounce" => Ok(EdgePolicy::Bounce),
+            _ => Err(format!(
+                "unknown edge policy {:?}; expected wrap, solid or bounce",
+                name
+            )),
+        }
+    }
+}
+
+// The `Board` describes the space the game is played in: how many grid cells
+// wide and tall it is, what happens at its edges, which of its cells are
+// blocked off by obstacles, and which are joined up by portals.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Board {
+    pub width: i16,
+    pub height: i16,
+    pub edges: EdgePolicy,
+    // One flag per cell, row by row, set where there's an obstacle. A board without
+    // any obstacles doesn't bother keeping a flag for every cell.
+    #[serde(default)]
+    obstacles: Vec<bool>,
+    // Pairs of cells that lead into each other. Moving into either cell of a pair
+    // takes you straight out of the other one.
+    #[serde(default)]
+    portals: Vec<(GridPosition, GridPosition)>,
+}
+
+impl Board {
+    // A new board wraps around at the edges, like the original game, and has
+    // nothing in the way.
+    pub fn new(width: i16, height: i16) -> Self {
+        Board {
+            width,
+            height,
+            edges: EdgePolicy::Wrap,
+            obstacles: Vec::new(),
+            portals: Vec::new(),
+        }
+    }
+
+    pub fn with_edges(self, edges: EdgePolicy) -> Self {
+        Board { edges, ..self }
+    }
+
+    // Adds obstacles in the given cells. Anything that isn't on the board is ignored.
+    pub fn with_obstacles<I: IntoIterator<Item = GridPosition>>(mut self, cells: I) -> Self {
+        for pos in cells {
+            self.set_obstacle(pos, true);
+        }
+        self
+    }
+
+    // Puts an obstacle in a cell, or clears one away.
+    pub fn set_obstacle(&mut self, pos: GridPosition, obstacle: bool) {
+        if !self.contains(pos) {
+            return;
+        }
+        if self.obstacles.is_empty() {
+            if !obstacle {
+                return;
+            }
+            self.obstacles = vec![false; self.cell_count()];
+        }
+        let index = self.index(pos);
+        self.obstacles[index] = obstacle;
+    }
+
+    // Whether there's an obstacle in a cell. Running into one is as deadly as
+    // running into a wall.
+    pub fn is_obstacle(&self, pos: GridPosition) -> bool {
+        self.contains(pos) && self.obstacles.get(self.index(pos)) == Some(&true)
+    }
+
+    // Every cell with an obstacle in it, row by row.
+    pub fn obstacles(&self) -> impl Iterator<Item = GridPosition> + '_ {
+        let width = self.width as usize;
+        self.obstacles
+            .iter()
+            .enumerate()
+            .filter(|&(_, &obstacle)| obstacle)
+            .map(move |(index, _)| {
+                GridPosition::new((index % width) as i16, (index / width) as i16)
+            })
+    }
+
+    // Joins two cells with a portal. Anything that isn't on the board is ignored.
+    pub fn add_portal(&mut self, a: GridPosition, b: GridPosition) {
+        if self.contains(a) && self.contains(b) && a != b {
+            self.portals.push((a, b));
+        }
+    }
+
+    // Every pair of cells joined by a portal, in the order they were added.
+    pub fn portals(&self) -> impl Iterator<Item = (GridPosition, GridPosition)> + '_ {
+        self.portals.iter().copied()
+    }
+
+    pub fn is_portal(&self, pos: GridPosition) -> bool {
+        self.portal_exit(pos).is_some()
+    }
+
+    // Where moving into `pos` really takes you, if it's one end of a portal.
+    pub fn portal_exit(&self, pos: GridPosition) -> Option<GridPosition> {
+        self.portals.iter().find_map(|&(a, b)| {
+            if pos == a {
+                Some(b)
+            } else if pos == b {
+                Some(a)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Cells are stored row by row.
+    fn index(&self, pos: GridPosition) -> usize {
+        pos.y as usize * self.width as usize + pos.x as usize
+    }
+
+    // Whether the edges of the board are walls rather than wrapping around.
+    pub fn has_walls(&self) -> bool {
+        self.edges != EdgePolicy::Wrap
+    }
+
+    // How many cells there are on the board in total.
+    pub fn cell_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    // Whether a position lies on the board.
+    pub fn contains(&self, pos: GridPosition) -> bool {
+        (0..self.width).contains(&pos.x) && (0..self.height).contains(&pos.y)
+    }
+
+    // Here we work out where moving one cell from `pos` in the direction `dir`
+    // takes us. On a wrapping board we always end up somewhere, but if the board
+    // has walls and the move would take us off the edge, we return `None`. If
+    // the cell we move into is a portal, we come out of the other end of it.
+    pub fn step(&self, pos: GridPosition, dir: Direction) -> Option<GridPosition> {
+        let next = match self.edges {
+            EdgePolicy::Wrap => GridPosition::new_from_move(pos, dir, self),
+            EdgePolicy::Solid | EdgePolicy::Bounce => {
+                let (dx, dy) = dir.delta();
+                let next = GridPosition::new(pos.x + dx, pos.y + dy);
+                if !self.contains(next) {
+                    return None;
+                }
+                next
+            }
+        };
+        Some(self.portal_exit(next).unwrap_or(next))
+    }
+
+    // Works out which way a single move from `from` to `to` went, taking wrapping
+    // into account, or returns `None` if the two cells aren't next to each other.
+    pub fn direction_between(&self, from: GridPosition, to: GridPosition) -> Option<Direction> {
+        Direction::ALL
+            .iter()
+            .copied()
+            .find(|&dir| self.step(from, dir) == Some(to))
+    }
+}
+
+// We default to a 30 x 20 game board.
+impl Default for Board {
+    fn default() -> Self {
+        Board::new(30, 20)
+    }
+}
+
+// A `BoardView` is everything the snake gets to see about the board when it
+// moves: the board itself and whatever is lying on it.
+#[derive(Clone, Copy, Debug)]
+pub struct BoardView<'a> {
+    pub board: &'a Board,
+    pub food: &'a FoodField,
+}
+
+impl<'a> BoardView<'a> {
+    pub fn new(board: &'a Board, food: &'a FoodField) -> Self {
+        BoardView { board, food }
+    }
+
+    // The food in the given cell, if there is any.
+    pub fn food_at(&self, pos: GridPosition) -> Option<&'a Food> {
+        self.food.get(pos)
+    }
+}
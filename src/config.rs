@@ -0,0 +1,270 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, EdgePolicy};
+use crate::difficulty::{Difficulty, DifficultyPreset};
+use crate::gamepad::DEFAULT_DEADZONE;
+use crate::level::{BuiltinLevel, Level};
+use crate::theme::BuiltinTheme;
+use crate::versus::MAX_PLAYERS;
+
+// The smallest board we're willing to play on. The snake starts out two cells
+// long a quarter of the way across the board, so anything narrower wouldn't fit it.
+pub const MIN_BOARD_SIZE: i16 = 4;
+// And the largest. Every snake keeps an `OccupancyGrid` with a few bytes for each
+// cell of the board, which at this size comes to around 8MB a snake. That
+// still leaves room for the 1000x1000 boards the grid was made for.
+pub const MAX_BOARD_SIZE: i16 = 1024;
+
+// `GameConfig` gathers up everything about a game that can be chosen at runtime
+// instead of being baked in: how big the board is, how big each cell is drawn, how
+// fast the game runs and speeds up, and how big the window is. It can be read from a TOML file,
+// where every setting is optional and falls back to its default.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    // The size of our game board in terms of how many grid cells it will take up.
+    pub board_width: i16,
+    pub board_height: i16,
+    // What happens when the snake reaches the edge of the board.
+    pub edges: EdgePolicy,
+    // The pixel size of each square tile.
+    pub cell_size: u16,
+    // How many pieces of food are on the board at once.
+    pub food_count: u32,
+    // The difficulty preset the title screen starts out on.
+    pub difficulty: DifficultyPreset,
+    // The level the title screen starts out on. Every level but the open one
+    // brings its own edges, so `edges` only matters on the open level. If a level
+    // file is given, as a path inside the game's resources, the level in it is
+    // used instead, and joins the built-in levels in the list the player can pick
+    // from. A level file brings its own board size too.
+    pub level: BuiltinLevel,
+    pub level_file: Option<String>,
+    // How many players the title screen starts out with. With more than one, they
+    // play a match against each other on the same keyboard, made up of rounds, and
+    // whoever wins `rounds_to_win` of them first wins the match.
+    pub players: usize,
+    pub rounds_to_win: u32,
+    // How far a controller's stick has to be pushed, from 0.0 to 1.0, to steer.
+    pub gamepad_deadzone: f32,
+    // Any of these that are set override the matching part of whichever
+    // difficulty preset the player picks.
+    pub updates_per_second: Option<f32>,
+    pub speedup_per_food: Option<f32>,
+    pub foods_per_level: Option<u32>,
+    pub speedup_per_level: Option<f32>,
+    pub max_updates_per_second: Option<f32>,
+    // The size of the window in pixels. If these aren't given, the window is made
+    // just big enough to fit the board. If they are, the board is scaled to fit.
+    pub window_width: Option<u32>,
+    pub window_height: Option<u32>,
+    // A tileset image to draw the snake and food with, as a path inside the game's
+    // resources. Without one, everything is drawn as plain colored rectangles.
+    pub tileset: Option<String>,
+    // The theme the game starts out with, which can be changed while playing. If a
+    // theme file is given, the theme in it is used instead, and joins the built-in
+    // themes in the list the player can switch between. Like the tileset, the theme
+    // file's path is inside the game's resources.
+    pub theme: BuiltinTheme,
+    pub theme_file: Option<String>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        let board = Board::default();
+        GameConfig {
+            board_width: board.width,
+            board_height: board.height,
+            edges: board.edges,
+            cell_size: 32,
+            food_count: 1,
+            difficulty: DifficultyPreset::default(),
+            level: BuiltinLevel::default(),
+            level_file: None,
+            players: 1,
+            rounds_to_win: 3,
+            gamepad_deadzone: DEFAULT_DEADZONE,
+            updates_per_second: None,
+            speedup_per_food: None,
+            foods_per_level: None,
+            speedup_per_level: None,
+            max_updates_per_second: None,
+            window_width: None,
+            window_height: None,
+            tileset: None,
+            theme: BuiltinTheme::default(),
+            theme_file: None,
+        }
+    }
+}
+
+// The ways reading a config can go wrong.
+#[derive(Debug)]
+pub enum ConfigError {
+    // The file isn't valid TOML, or has settings of the wrong type.
+    Parse(toml::de::Error),
+    // The file is well formed, but asks for something we can't do.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Parse(e) => write!(f, "couldn't parse config: {}", e),
+            ConfigError::Invalid(message) => write!(f, "invalid config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl GameConfig {
+    // Reads a config from TOML and makes sure it's something we can play with.
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: GameConfig = toml::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Checks that every setting is within a range the game can handle.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let board_range = MIN_BOARD_SIZE..=MAX_BOARD_SIZE;
+        if !board_range.contains(&self.board_width) || !board_range.contains(&self.board_height) {
+            return Err(ConfigError::Invalid(format!(
+                "the board must be between {} and {} cells on each side, not {}x{}",
+                MIN_BOARD_SIZE, MAX_BOARD_SIZE, self.board_width, self.board_height
+            )));
+        }
+        if self.cell_size == 0 {
+            return Err(ConfigError::Invalid(
+                "cells must be at least one pixel".to_string(),
+            ));
+        }
+        if self.food_count == 0 {
+            return Err(ConfigError::Invalid(
+                "there must be at least one piece of food on the board".to_string(),
+            ));
+        }
+        if !(1..=MAX_PLAYERS).contains(&self.players) {
+            return Err(ConfigError::Invalid(format!(
+                "there must be between 1 and {} players, not {}",
+                MAX_PLAYERS, self.players
+            )));
+        }
+        if self.rounds_to_win == 0 {
+            return Err(ConfigError::Invalid(
+                "a match must take at least one round to win".to_string(),
+            ));
+        }
+        if !(0.0..1.0).contains(&self.gamepad_deadzone) {
+            return Err(ConfigError::Invalid(format!(
+                "the gamepad deadzone must be at least 0 and less than 1, not {}",
+                self.gamepad_deadzone
+            )));
+        }
+        if self.foods_per_level == Some(0) {
+            return Err(ConfigError::Invalid(
+                "a level must take at least one piece of food".to_string(),
+            ));
+        }
+        self.difficulty_curve(self.difficulty)
+            .validate()
+            .map_err(ConfigError::Invalid)?;
+        self.level(self.level)
+            .validate()
+            .map_err(ConfigError::Invalid)?;
+        if self.window_width == Some(0) || self.window_height == Some(0) {
+            return Err(ConfigError::Invalid(
+                "the window must be at least one pixel".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn board(&self) -> Board {
+        Board::new(self.board_width, self.board_height).with_edges(self.edges)
+    }
+
+    // A built-in level laid out on a board the size this config asks for.
+    pub fn level(&self, preset: BuiltinLevel) -> Level {
+        preset.level(self.board_width, self.board_height, self.edges)
+    }
+
+    // The speed curve for a difficulty preset, with any overrides from the
+    // config applied on top.
+    pub fn difficulty_curve(&self, preset: DifficultyPreset) -> Difficulty {
+        let preset = preset.difficulty();
+        Difficulty {
+            start_speed: self.updates_per_second.unwrap_or(preset.start_speed),
+            speedup_per_food: self.speedup_per_food.unwrap_or(preset.speedup_per_food),
+            foods_per_level: self.foods_per_level.unwrap_or(preset.foods_per_level),
+            speedup_per_level: self.speedup_per_level.unwrap_or(preset.speedup_per_level),
+            max_speed: self.max_updates_per_second.unwrap_or(preset.max_speed),
+        }
+    }
+
+    // How many pixels the whole board takes up when drawn at `cell_size`.
+    pub fn board_pixel_size(&self) -> (u32, u32) {
+        (
+            self.board_width as u32 * self.cell_size as u32,
+            self.board_height as u32 * self.cell_size as u32,
+        )
+    }
+
+    // How big the window should be, which unless we've been told otherwise is
+    // just big enough for the board.
+    pub fn window_size(&self) -> (u32, u32) {
+        let (board_width, board_height) = self.board_pixel_size();
+        (
+            self.window_width.unwrap_or(board_width),
+            self.window_height.unwrap_or(board_height),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sized(width: i16, height: i16) -> GameConfig {
+        GameConfig {
+            board_width: width,
+            board_height: height,
+            ..GameConfig::default()
+        }
+    }
+
+    #[test]
+    fn boards_up_to_the_limit_are_allowed() {
+        assert!(sized(MAX_BOARD_SIZE, MAX_BOARD_SIZE).validate().is_ok());
+        assert!(sized(MIN_BOARD_SIZE, MIN_BOARD_SIZE).validate().is_ok());
+    }
+
+    #[test]
+    fn boards_past_the_limit_are_refused() {
+        assert!(sized(MAX_BOARD_SIZE + 1, MAX_BOARD_SIZE)
+            .validate()
+            .is_err());
+        assert!(sized(MAX_BOARD_SIZE, MIN_BOARD_SIZE - 1)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn a_board_at_the_limit_can_be_played() {
+        let config = sized(MAX_BOARD_SIZE, MAX_BOARD_SIZE);
+        let level = config.level(config.level);
+        let mut sim = crate::simulation::Simulation::from_level(level, 7);
+        sim.step(&[]);
+        assert_eq!(sim.board().cell_count(), 1024 * 1024);
+    }
+
+    #[test]
+    fn reads_a_board_size_from_toml() {
+        let config = GameConfig::from_toml("board_width = 1024\nboard_height = 4\n").unwrap();
+        assert_eq!((config.board_width, config.board_height), (1024, 4));
+        assert!(GameConfig::from_toml("board_width = 1025\n").is_err());
+    }
+}
@@ -0,0 +1,481 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigError;
+use crate::grid::{Direction, Rotation};
+use crate::snake::Steer;
+use crate::versus::MAX_PLAYERS;
+
+// The things a player can do with a key while a game is on. What each one does is
+// up to the frontend; all we decide here is which keys do it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    // Steer the snake this way.
+    Turn(Direction),
+    // Pause the game, or carry on with it.
+    Pause,
+    // Start the game over.
+    Restart,
+    // Give up on the game.
+    Quit,
+    // Run the game faster for as long as the key is held down.
+    SpeedBoost,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::Turn(Direction::Up),
+        Action::Turn(Direction::Down),
+        Action::Turn(Direction::Left),
+        Action::Turn(Direction::Right),
+        Action::Pause,
+        Action::Restart,
+        Action::Quit,
+        Action::SpeedBoost,
+    ];
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Turn(dir) => write!(f, "{}", dir),
+            Action::Pause => f.write_str("pause"),
+            Action::Restart => f.write_str("restart"),
+            Action::Quit => f.write_str("quit"),
+            Action::SpeedBoost => f.write_str("speed-boost"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown action {:?}; expected up, down, left, right, pause, restart, quit or speed-boost",
+                    name
+                )
+            })
+    }
+}
+
+// How a player's turn keys steer their snake. Each player picks their own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ControlMode {
+    // Each of the four keys turns the snake to face its own direction.
+    #[default]
+    Absolute,
+    // Only the left and right keys do anything: they turn the snake a quarter turn
+    // left or right from the way it's heading. That only needs two keys, which
+    // makes it easy to play with one hand.
+    Relative,
+}
+
+impl ControlMode {
+    pub const ALL: [ControlMode; 2] = [ControlMode::Absolute, ControlMode::Relative];
+
+    // How pressing a player's key for `dir` steers their snake in this mode, if
+    // it does at all.
+    pub fn steer(&self, dir: Direction) -> Option<Steer> {
+        match (self, dir) {
+            (ControlMode::Absolute, dir) => Some(Steer::Face(dir)),
+            (ControlMode::Relative, Direction::Left) => {
+                Some(Steer::Rotate(Rotation::CounterClockwise))
+            }
+            (ControlMode::Relative, Direction::Right) => Some(Steer::Rotate(Rotation::Clockwise)),
+            (ControlMode::Relative, _) => None,
+        }
+    }
+}
+
+impl fmt::Display for ControlMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlMode::Absolute => f.write_str("absolute"),
+            ControlMode::Relative => f.write_str("relative"),
+        }
+    }
+}
+
+impl FromStr for ControlMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        ControlMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown control mode {:?}; expected absolute or relative",
+                    name
+                )
+            })
+    }
+}
+
+// The keys bound to each action for a single player. Keys are written by name, the
+// way the frontend names them; for ggez that's the name of its `KeyCode`, like
+// `"Up"`, `"W"`, `"Space"` or `"Key1"`. In a file, every action is optional, and
+// one that's left out has no keys at all. Along with the keys goes how the player
+// likes to steer.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PlayerBindings {
+    pub mode: ControlMode,
+    pub up: Vec<String>,
+    pub down: Vec<String>,
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    pub pause: Vec<String>,
+    pub restart: Vec<String>,
+    pub quit: Vec<String>,
+    pub speed_boost: Vec<String>,
+}
+
+impl PlayerBindings {
+    pub fn keys(&self, action: Action) -> &[String] {
+        match action {
+            Action::Turn(Direction::Up) => &self.up,
+            Action::Turn(Direction::Down) => &self.down,
+            Action::Turn(Direction::Left) => &self.left,
+            Action::Turn(Direction::Right) => &self.right,
+            Action::Pause => &self.pause,
+            Action::Restart => &self.restart,
+            Action::Quit => &self.quit,
+            Action::SpeedBoost => &self.speed_boost,
+        }
+    }
+
+    fn keys_mut(&mut self, action: Action) -> &mut Vec<String> {
+        match action {
+            Action::Turn(Direction::Up) => &mut self.up,
+            Action::Turn(Direction::Down) => &mut self.down,
+            Action::Turn(Direction::Left) => &mut self.left,
+            Action::Turn(Direction::Right) => &mut self.right,
+            Action::Pause => &mut self.pause,
+            Action::Restart => &mut self.restart,
+            Action::Quit => &mut self.quit,
+            Action::SpeedBoost => &mut self.speed_boost,
+        }
+    }
+}
+
+// Two bindings that share a key, so pressing it couldn't do both. Each binding is
+// a player, counted from 0, and the action that player has the key bound to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub key: String,
+    pub first: (usize, Action),
+    pub second: (usize, Action),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is bound to both player {} {} and player {} {}",
+            self.key,
+            self.first.0 + 1,
+            self.first.1,
+            self.second.0 + 1,
+            self.second.1
+        )
+    }
+}
+
+// `KeyBindings` holds every player's bindings, and answers which action a key
+// stands for. Any key can be bound to as many actions as we like, so long as no
+// key is bound twice: that way every key press means exactly one thing. In a file,
+// each player's bindings go in their own `[[players]]` table, in player order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    players: Vec<PlayerBindings>,
+}
+
+// By default, the first player steers with the arrow keys or with H, J, K and L,
+// the way vi moves around, and the second player with W, A, S and D. R or Enter
+// restarts. Playing on
+// our own, every player's keys steer our snake, so all three work.
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let keys = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        KeyBindings {
+            players: vec![
+                PlayerBindings {
+                    mode: ControlMode::Absolute,
+                    up: keys(&["Up", "K"]),
+                    down: keys(&["Down", "J"]),
+                    left: keys(&["Left", "H"]),
+                    right: keys(&["Right", "L"]),
+                    pause: keys(&["P", "Escape"]),
+                    restart: keys(&["R", "Return"]),
+                    quit: keys(&["Q"]),
+                    speed_boost: keys(&["Space"]),
+                },
+                PlayerBindings {
+                    up: keys(&["W"]),
+                    down: keys(&["S"]),
+                    left: keys(&["A"]),
+                    right: keys(&["D"]),
+                    ..PlayerBindings::default()
+                },
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    // Reads bindings from TOML. A file that binds the same key twice is refused,
+    // since we'd have no way of knowing which binding was meant. Players the file
+    // leaves out have no keys at all.
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let mut bindings: KeyBindings = toml::from_str(text).map_err(ConfigError::Parse)?;
+        if bindings.players.len() > MAX_PLAYERS {
+            return Err(ConfigError::Invalid(format!(
+                "there are bindings for {} players, but at most {} can play",
+                bindings.players.len(),
+                MAX_PLAYERS
+            )));
+        }
+        bindings
+            .players
+            .resize(MAX_PLAYERS, PlayerBindings::default());
+        if let Some(conflict) = bindings.conflicts().into_iter().next() {
+            return Err(ConfigError::Invalid(conflict.to_string()));
+        }
+        Ok(bindings)
+    }
+
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    // How many players there are bindings for.
+    pub fn players(&self) -> usize {
+        self.players.len()
+    }
+
+    // The keys a player has bound to an action.
+    pub fn keys(&self, player: usize, action: Action) -> &[String] {
+        self.players
+            .get(player)
+            .map_or(&[], |bindings| bindings.keys(action))
+    }
+
+    // How a player steers.
+    pub fn mode(&self, player: usize) -> ControlMode {
+        self.players
+            .get(player)
+            .map_or(ControlMode::default(), |bindings| bindings.mode)
+    }
+
+    pub fn set_mode(&mut self, player: usize, mode: ControlMode) {
+        if let Some(bindings) = self.players.get_mut(player) {
+            bindings.mode = mode;
+        }
+    }
+
+    // The player and action a key is bound to, if it's bound at all.
+    pub fn action(&self, key: &str) -> Option<(usize, Action)> {
+        self.bindings()
+            .find(|&(k, _, _)| k == key)
+            .map(|(_, player, action)| (player, action))
+    }
+
+    // Binds another key to a player's action. If the key is already bound to
+    // something else, we leave the bindings alone and say what it's bound to.
+    pub fn bind(&mut self, player: usize, action: Action, key: &str) -> Result<(), Conflict> {
+        match self.action(key) {
+            Some(existing) if existing == (player, action) => return Ok(()),
+            Some(existing) => {
+                return Err(Conflict {
+                    key: key.to_string(),
+                    first: existing,
+                    second: (player, action),
+                })
+            }
+            None => (),
+        }
+        if let Some(bindings) = self.players.get_mut(player) {
+            bindings.keys_mut(action).push(key.to_string());
+        }
+        Ok(())
+    }
+
+    // Takes every key off a player's action.
+    pub fn clear(&mut self, player: usize, action: Action) {
+        if let Some(bindings) = self.players.get_mut(player) {
+            bindings.keys_mut(action).clear();
+        }
+    }
+
+    // Every key that's bound more than once, paired with the first binding it has.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let all: Vec<(&str, usize, Action)> = self.bindings().collect();
+        let mut conflicts = Vec::new();
+        for (i, &(key, player, action)) in all.iter().enumerate() {
+            if let Some(&(_, first_player, first_action)) =
+                all[..i].iter().find(|&&(other, _, _)| other == key)
+            {
+                conflicts.push(Conflict {
+                    key: key.to_string(),
+                    first: (first_player, first_action),
+                    second: (player, action),
+                });
+            }
+        }
+        conflicts
+    }
+
+    // Every binding there is, as the key, the player and the action, in player order
+    // and then in the order of `Action::ALL`.
+    fn bindings(&self) -> impl Iterator<Item = (&str, usize, Action)> {
+        self.players
+            .iter()
+            .enumerate()
+            .flat_map(|(player, bindings)| {
+                Action::ALL.iter().flat_map(move |&action| {
+                    bindings
+                        .keys(action)
+                        .iter()
+                        .map(move |key| (key.as_str(), player, action))
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_defaults_bind_every_key_once() {
+        let defaults = KeyBindings::default();
+        assert_eq!(defaults.conflicts(), vec![]);
+        assert_eq!(defaults.players(), MAX_PLAYERS);
+        assert_eq!(defaults.action("K"), Some((0, Action::Turn(Direction::Up))));
+        assert_eq!(
+            defaults.action("A"),
+            Some((1, Action::Turn(Direction::Left)))
+        );
+        assert_eq!(defaults.action("Return"), Some((0, Action::Restart)));
+        assert_eq!(defaults.action("Tab"), None);
+    }
+
+    #[test]
+    fn steers_the_way_the_player_picked() {
+        for &dir in Direction::ALL.iter() {
+            assert_eq!(ControlMode::Absolute.steer(dir), Some(Steer::Face(dir)));
+        }
+        // In relative mode, only left and right do anything, and they turn rather
+        // than face.
+        assert_eq!(
+            ControlMode::Relative.steer(Direction::Left),
+            Some(Steer::Rotate(Rotation::CounterClockwise))
+        );
+        assert_eq!(
+            ControlMode::Relative.steer(Direction::Right),
+            Some(Steer::Rotate(Rotation::Clockwise))
+        );
+        assert_eq!(ControlMode::Relative.steer(Direction::Up), None);
+        assert_eq!(ControlMode::Relative.steer(Direction::Down), None);
+    }
+
+    #[test]
+    fn refuses_to_bind_a_key_twice() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(
+            bindings.bind(1, Action::Pause, "K"),
+            Err(Conflict {
+                key: "K".to_string(),
+                first: (0, Action::Turn(Direction::Up)),
+                second: (1, Action::Pause),
+            })
+        );
+        // Nothing changed, so the key still does what it did.
+        assert_eq!(bindings.keys(1, Action::Pause), &[] as &[String]);
+        assert_eq!(bindings.action("K"), Some((0, Action::Turn(Direction::Up))));
+        // Binding a key to what it's already bound to is fine, and doesn't add it
+        // again.
+        assert_eq!(bindings.bind(0, Action::Turn(Direction::Up), "K"), Ok(()));
+        assert_eq!(bindings.keys(0, Action::Turn(Direction::Up)).len(), 2);
+        // Once it's been taken off, it can go somewhere else.
+        bindings.clear(0, Action::Turn(Direction::Up));
+        assert_eq!(bindings.bind(1, Action::Pause, "K"), Ok(()));
+        assert_eq!(bindings.action("K"), Some((1, Action::Pause)));
+    }
+
+    #[test]
+    fn finds_every_key_bound_more_than_once() {
+        let mut bindings = KeyBindings::default();
+        bindings.players[1].up.push("Up".to_string());
+        bindings.players[1].pause.push("Space".to_string());
+        let keys: Vec<String> = bindings
+            .conflicts()
+            .into_iter()
+            .map(|conflict| conflict.key)
+            .collect();
+        assert_eq!(keys, vec!["Up".to_string(), "Space".to_string()]);
+    }
+
+    #[test]
+    fn refuses_a_file_that_binds_a_key_twice() {
+        let text = "[[players]]\nup = [\"W\"]\n\n[[players]]\ndown = [\"W\"]\n";
+        match KeyBindings::from_toml(text) {
+            Err(ConfigError::Invalid(message)) => assert!(message.contains("W")),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        // Even twice for the same thing.
+        let text = "[[players]]\nup = [\"W\", \"W\"]\n";
+        assert!(matches!(
+            KeyBindings::from_toml(text),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_a_file_with_too_many_players() {
+        let text = "[[players]]\n".repeat(MAX_PLAYERS + 1);
+        assert!(matches!(
+            KeyBindings::from_toml(&text),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(KeyBindings::from_toml(&"[[players]]\n".repeat(MAX_PLAYERS)).is_ok());
+    }
+
+    #[test]
+    fn players_left_out_of_a_file_have_no_keys() {
+        let text = "[[players]]\nmode = \"relative\"\nleft = [\"Z\"]\nright = [\"X\"]\n";
+        let bindings = KeyBindings::from_toml(text).unwrap();
+        assert_eq!(bindings.players(), MAX_PLAYERS);
+        assert_eq!(bindings.mode(0), ControlMode::Relative);
+        assert_eq!(bindings.keys(0, Action::Turn(Direction::Left)), &["Z"]);
+        assert_eq!(bindings.keys(0, Action::Pause), &[] as &[String]);
+        for player in 1..MAX_PLAYERS {
+            assert!(Action::ALL
+                .iter()
+                .all(|&action| bindings.keys(player, action).is_empty()));
+        }
+        // A file that doesn't list any players at all is the defaults, though.
+        assert_eq!(KeyBindings::from_toml("").unwrap(), KeyBindings::default());
+    }
+
+    #[test]
+    fn bindings_survive_a_round_trip() {
+        let mut bindings = KeyBindings::default();
+        bindings.set_mode(1, ControlMode::Relative);
+        bindings.bind(1, Action::SpeedBoost, "LShift").unwrap();
+        let text = bindings.to_toml().unwrap();
+        assert_eq!(KeyBindings::from_toml(&text).unwrap(), bindings);
+    }
+}
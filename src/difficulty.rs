@@ -0,0 +1,282 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+// A `Difficulty` describes how fast the game runs and how quickly it speeds up
+// as the snake eats. Speeds are measured in updates per second.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Difficulty {
+    // How fast the game runs before the snake has eaten anything.
+    pub start_speed: f32,
+    // How much faster the game gets with every piece of food.
+    pub speedup_per_food: f32,
+    // Every time the snake eats this many pieces of food, it goes up a level...
+    pub foods_per_level: u32,
+    // ...and the game gets this much faster on top of the per-food speedup.
+    pub speedup_per_level: f32,
+    // However much the snake eats, the game never runs faster than this.
+    pub max_speed: f32,
+}
+
+impl Difficulty {
+    // Which level the player is on after eating `foods_eaten` pieces of food,
+    // starting from level 1.
+    pub fn level(&self, foods_eaten: u32) -> u32 {
+        1 + foods_eaten / self.foods_per_level.max(1)
+    }
+
+    // How many updates per second the game runs at after eating `foods_eaten`
+    // pieces of food.
+    pub fn speed(&self, foods_eaten: u32) -> f32 {
+        let levels_gained = (self.level(foods_eaten) - 1) as f32;
+        let speed = self.start_speed
+            + self.speedup_per_food * foods_eaten as f32
+            + self.speedup_per_level * levels_gained;
+        speed.min(self.max_speed)
+    }
+
+    // Checks that every speed is one the game can actually run at, and that the
+    // game never has to slow down to stay under its max speed.
+    pub fn validate(&self) -> Result<(), String> {
+        let speeds = [
+            ("start speed", self.start_speed),
+            ("max speed", self.max_speed),
+        ];
+        for &(name, speed) in speeds.iter() {
+            if !(speed.is_finite() && speed > 0.0) {
+                return Err(format!(
+                    "the {} must be a positive number of updates per second, not {}",
+                    name, speed
+                ));
+            }
+        }
+        if self.max_speed < self.start_speed {
+            return Err(format!(
+                "the max speed ({}) can't be slower than the start speed ({})",
+                self.max_speed, self.start_speed
+            ));
+        }
+        let speedups = [
+            ("speedup per food", self.speedup_per_food),
+            ("speedup per level", self.speedup_per_level),
+        ];
+        for &(name, speedup) in speedups.iter() {
+            if !(speedup.is_finite() && speedup >= 0.0) {
+                return Err(format!(
+                    "the {} can't be negative, but it was {}",
+                    name, speedup
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        DifficultyPreset::default().difficulty()
+    }
+}
+
+// These are the difficulties the player can pick from on the title screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DifficultyPreset {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl DifficultyPreset {
+    pub const ALL: [DifficultyPreset; 4] = [
+        DifficultyPreset::Easy,
+        DifficultyPreset::Normal,
+        DifficultyPreset::Hard,
+        DifficultyPreset::Insane,
+    ];
+
+    // The speed curve each preset stands for. Normal starts at the speed the
+    // game has always run at.
+    pub fn difficulty(&self) -> Difficulty {
+        match *self {
+            DifficultyPreset::Easy => Difficulty {
+                start_speed: 5.0,
+                speedup_per_food: 0.05,
+                foods_per_level: 5,
+                speedup_per_level: 0.5,
+                max_speed: 10.0,
+            },
+            DifficultyPreset::Normal => Difficulty {
+                start_speed: 8.0,
+                speedup_per_food: 0.1,
+                foods_per_level: 5,
+                speedup_per_level: 1.0,
+                max_speed: 20.0,
+            },
+            DifficultyPreset::Hard => Difficulty {
+                start_speed: 10.0,
+                speedup_per_food: 0.2,
+                foods_per_level: 4,
+                speedup_per_level: 1.5,
+                max_speed: 25.0,
+            },
+            DifficultyPreset::Insane => Difficulty {
+                start_speed: 15.0,
+                speedup_per_food: 0.3,
+                foods_per_level: 3,
+                speedup_per_level: 2.0,
+                max_speed: 40.0,
+            },
+        }
+    }
+
+    // The next harder and next easier presets, stopping at either end.
+    pub fn harder(&self) -> Self {
+        let index = DifficultyPreset::ALL
+            .iter()
+            .position(|p| p == self)
+            .unwrap_or(0);
+        DifficultyPreset::ALL[(index + 1).min(DifficultyPreset::ALL.len() - 1)]
+    }
+
+    pub fn easier(&self) -> Self {
+        let index = DifficultyPreset::ALL
+            .iter()
+            .position(|p| p == self)
+            .unwrap_or(0);
+        DifficultyPreset::ALL[index.saturating_sub(1)]
+    }
+}
+
+impl fmt::Display for DifficultyPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DifficultyPreset::Easy => "easy",
+            DifficultyPreset::Normal => "normal",
+            DifficultyPreset::Hard => "hard",
+            DifficultyPreset::Insane => "insane",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for DifficultyPreset {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        DifficultyPreset::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown difficulty {:?}; expected easy, normal, hard or insane",
+                    name
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speeds_up_with_every_piece_of_food() {
+        for preset in DifficultyPreset::ALL.iter() {
+            let difficulty = preset.difficulty();
+            let speeds: Vec<f32> = (0..300).map(|eaten| difficulty.speed(eaten)).collect();
+            assert_eq!(speeds[0], difficulty.start_speed, "{}", preset);
+            assert!(
+                speeds.windows(2).all(|pair| pair[1] >= pair[0]),
+                "{} slowed down",
+                preset
+            );
+        }
+    }
+
+    #[test]
+    fn never_goes_past_the_max_speed() {
+        for preset in DifficultyPreset::ALL.iter() {
+            let difficulty = preset.difficulty();
+            assert_eq!(difficulty.speed(10_000), difficulty.max_speed, "{}", preset);
+        }
+    }
+
+    #[test]
+    fn goes_up_a_level_every_few_pieces_of_food() {
+        let difficulty = DifficultyPreset::Normal.difficulty();
+        let levels: Vec<u32> = [0, 4, 5, 9, 10, 14, 15]
+            .iter()
+            .map(|&eaten| difficulty.level(eaten))
+            .collect();
+        assert_eq!(levels, vec![1, 1, 2, 2, 3, 3, 4]);
+        // Going up a level is a speedup of its own, on top of the one for the food.
+        let step = difficulty.speed(5) - difficulty.speed(4);
+        let expected = difficulty.speedup_per_food + difficulty.speedup_per_level;
+        assert!((step - expected).abs() < 1e-5);
+        // A level that takes no food at all still counts as taking one.
+        let every_food = Difficulty {
+            foods_per_level: 0,
+            ..difficulty
+        };
+        assert_eq!(every_food.level(3), 4);
+    }
+
+    #[test]
+    fn the_presets_are_valid() {
+        for preset in DifficultyPreset::ALL.iter() {
+            assert_eq!(preset.difficulty().validate(), Ok(()), "{}", preset);
+        }
+    }
+
+    #[test]
+    fn refuses_speeds_the_game_cant_run_at() {
+        let normal = Difficulty::default();
+        let invalid = [
+            Difficulty {
+                start_speed: 0.0,
+                ..normal
+            },
+            Difficulty {
+                max_speed: -1.0,
+                ..normal
+            },
+            Difficulty {
+                start_speed: f32::NAN,
+                ..normal
+            },
+            Difficulty {
+                max_speed: f32::INFINITY,
+                ..normal
+            },
+            Difficulty {
+                speedup_per_food: -0.1,
+                ..normal
+            },
+            // A max speed below the start speed is the wrong way round.
+            Difficulty {
+                start_speed: 12.0,
+                max_speed: 6.0,
+                ..normal
+            },
+        ];
+        for difficulty in invalid.iter() {
+            assert!(difficulty.validate().is_err(), "{:?}", difficulty);
+        }
+    }
+
+    #[test]
+    fn steps_through_the_presets_and_stops_at_either_end() {
+        assert_eq!(DifficultyPreset::Easy.easier(), DifficultyPreset::Easy);
+        assert_eq!(DifficultyPreset::Easy.harder(), DifficultyPreset::Normal);
+        assert_eq!(DifficultyPreset::Insane.harder(), DifficultyPreset::Insane);
+        for preset in DifficultyPreset::ALL.iter() {
+            assert_eq!(preset.to_string().parse(), Ok(*preset));
+        }
+        assert!("nightmare".parse::<DifficultyPreset>().is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use crate::grid::GridPosition;
+use crate::levelfile::{LevelMap, MapTile};
+
+// How many changes the editor remembers for undoing. Past this, the oldest ones
+// are forgotten.
+pub const MAX_UNDO: usize = 100;
+
+// The `LevelEditor` is everything the level editor does that doesn't involve a
+// mouse or a screen: painting tiles onto a map and undoing and redoing changes.
+// Changes are made in strokes, so that everything painted while the mouse button
+// was held down is undone in one go, the way a player would expect.
+#[derive(Clone, Debug)]
+pub struct LevelEditor {
+    map: LevelMap,
+    // What the map looked like before each change, most recent last...
+    undo: Vec<LevelMap>,
+    // ...and after each change we've undone, most recently undone last.
+    redo: Vec<LevelMap>,
+    // While a stroke is being painted, the tile it paints and the last cell it
+    // painted, if it's painted one yet.
+    stroke: Option<(MapTile, Option<GridPosition>)>,
+}
+
+impl LevelEditor {
+    pub fn new(map: LevelMap) -> Self {
+        LevelEditor {
+            map,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            stroke: None,
+        }
+    }
+
+    pub fn map(&self) -> &LevelMap {
+        &self.map
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn is_painting(&self) -> bool {
+        self.stroke.is_some()
+    }
+
+    // Starts painting `tile`. Nothing is painted until the stroke is moved to a cell.
+    pub fn begin_stroke(&mut self, tile: MapTile) {
+        self.end_stroke();
+        self.checkpoint();
+        self.stroke = Some((tile, None));
+    }
+
+    // Carries the stroke on to `pos`. If the mouse has moved more than a cell since
+    // the last time, we paint every cell in between as well, so a quick stroke
+    // doesn't leave gaps. Returns whether anything changed.
+    pub fn stroke_to(&mut self, pos: GridPosition) -> bool {
+        let (tile, last) = match self.stroke {
+            Some(stroke) => stroke,
+            None => return false,
+        };
+        let mut changed = false;
+        for cell in cells_between(last.unwrap_or(pos), pos) {
+            changed |= self.paint(cell, tile);
+        }
+        self.stroke = Some((tile, Some(pos)));
+        changed
+    }
+
+    // Finishes the stroke. If it didn't change anything, there's nothing to undo.
+    pub fn end_stroke(&mut self) {
+        if self.stroke.take().is_some() {
+            self.commit();
+        }
+    }
+
+    // Makes a change to the map other than painting, like turning the start around
+    // or loading a whole new map, as a single step that can be undone.
+    pub fn edit<F: FnOnce(&mut LevelMap)>(&mut self, change: F) {
+        self.end_stroke();
+        self.checkpoint();
+        change(&mut self.map);
+        self.commit();
+    }
+
+    // Goes back to how the map was before the last change, and returns whether
+    // there was one to undo.
+    pub fn undo(&mut self) -> bool {
+        self.end_stroke();
+        match self.undo.pop() {
+            Some(map) => {
+                self.redo.push(std::mem::replace(&mut self.map, map));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Makes the last change we undid again, and returns whether there was one.
+    pub fn redo(&mut self) -> bool {
+        self.end_stroke();
+        match self.redo.pop() {
+            Some(map) => {
+                self.undo.push(std::mem::replace(&mut self.map, map));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Paints a single cell. A map only has one start, so painting the start
+    // somewhere moves it there from wherever it was.
+    fn paint(&mut self, pos: GridPosition, tile: MapTile) -> bool {
+        if !self.map.contains(pos) {
+            return false;
+        }
+        let mut changed = false;
+        if tile == MapTile::Start {
+            let starts: Vec<GridPosition> = self
+                .map
+                .tiles()
+                .filter(|&(other, t)| t == MapTile::Start && other != pos)
+                .map(|(other, _)| other)
+                .collect();
+            for other in starts {
+                changed |= self.map.set(other, MapTile::Empty);
+            }
+        }
+        changed | self.map.set(pos, tile)
+    }
+
+    // Remembers how the map looks now, before we change it.
+    fn checkpoint(&mut self) {
+        self.undo.push(self.map.clone());
+    }
+
+    // Once a change is done, we either forget the checkpoint from before it, if it
+    // didn't actually change anything, or forget the changes we'd undone, since
+    // they can't be redone on top of this one. Only a change that's kept can push
+    // the oldest one out of the history; a click that paints nothing new mustn't.
+    fn commit(&mut self) {
+        if self.undo.last() == Some(&self.map) {
+            self.undo.pop();
+        } else {
+            self.redo.clear();
+            if self.undo.len() > MAX_UNDO {
+                self.undo.remove(0);
+            }
+        }
+    }
+}
+
+// Every cell on a straight line from `from` to `to`, not counting `from` unless
+// the two are the same cell.
+fn cells_between(from: GridPosition, to: GridPosition) -> Vec<GridPosition> {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    let steps = dx.abs().max(dy.abs());
+    if steps == 0 {
+        return vec![to];
+    }
+    (1..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            GridPosition::new(
+                from.x + (dx as f32 * t).round() as i16,
+                from.y + (dy as f32 * t).round() as i16,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paint(editor: &mut LevelEditor, tile: MapTile, cells: &[(i16, i16)]) {
+        editor.begin_stroke(tile);
+        for &cell in cells.iter() {
+            editor.stroke_to(cell.into());
+        }
+        editor.end_stroke();
+    }
+
+    #[test]
+    fn undoes_and_redoes_a_whole_stroke() {
+        let blank = LevelMap::new(6, 4);
+        let mut editor = LevelEditor::new(blank.clone());
+        paint(&mut editor, MapTile::Wall, &[(0, 0), (3, 0)]);
+        let painted = editor.map().clone();
+        for x in 0..4 {
+            assert_eq!(painted.get(GridPosition::new(x, 0)), Some(MapTile::Wall));
+        }
+
+        assert!(editor.undo());
+        assert_eq!(editor.map(), &blank);
+        assert!(!editor.can_undo());
+        assert!(editor.redo());
+        assert_eq!(editor.map(), &painted);
+        assert!(!editor.can_redo());
+        assert!(!editor.redo());
+    }
+
+    #[test]
+    fn a_new_change_forgets_what_was_undone() {
+        let mut editor = LevelEditor::new(LevelMap::new(6, 4));
+        paint(&mut editor, MapTile::Wall, &[(1, 1)]);
+        editor.undo();
+        assert!(editor.can_redo());
+        // Painting over what's already there doesn't count as a change...
+        paint(&mut editor, MapTile::Empty, &[(1, 1)]);
+        assert!(editor.can_redo());
+        // ...but anything else does.
+        paint(&mut editor, MapTile::Food, &[(2, 2)]);
+        assert!(!editor.can_redo());
+        assert!(editor.undo());
+        assert!(!editor.can_undo());
+    }
+
+    #[test]
+    fn moves_the_start_rather_than_adding_another() {
+        let mut editor = LevelEditor::new(LevelMap::new(6, 4));
+        paint(&mut editor, MapTile::Start, &[(1, 1)]);
+        paint(&mut editor, MapTile::Start, &[(4, 2)]);
+        let starts: Vec<GridPosition> = editor
+            .map()
+            .tiles()
+            .filter(|&(_, tile)| tile == MapTile::Start)
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(starts, vec![GridPosition::new(4, 2)]);
+    }
+
+    #[test]
+    fn forgets_the_oldest_changes_past_the_limit() {
+        let mut editor = LevelEditor::new(LevelMap::new(MAX_UNDO + 10, 1));
+        for x in 0..MAX_UNDO + 10 {
+            paint(&mut editor, MapTile::Wall, &[(x as i16, 0)]);
+        }
+        let mut undone = 0;
+        while editor.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO);
+        // The first ten walls can't be undone any more.
+        assert_eq!(
+            editor.map().get(GridPosition::new(9, 0)),
+            Some(MapTile::Wall)
+        );
+        assert_eq!(
+            editor.map().get(GridPosition::new(10, 0)),
+            Some(MapTile::Empty)
+        );
+    }
+
+    #[test]
+    fn a_stroke_that_changes_nothing_keeps_the_whole_history() {
+        let mut editor = LevelEditor::new(LevelMap::new(MAX_UNDO, 1));
+        for x in 0..MAX_UNDO {
+            paint(&mut editor, MapTile::Wall, &[(x as i16, 0)]);
+        }
+        // Going over a wall again with the wall brush shouldn't push the first
+        // wall out of the history.
+        paint(&mut editor, MapTile::Wall, &[(0, 0)]);
+        editor.edit(|_| ());
+        let mut undone = 0;
+        while editor.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO);
+        assert_eq!(editor.map(), &LevelMap::new(MAX_UNDO, 1));
+    }
+
+    #[test]
+    fn fills_in_the_cells_a_quick_stroke_skipped() {
+        let cells = |from: (i16, i16), to: (i16, i16)| -> Vec<(i16, i16)> {
+            cells_between(from.into(), to.into())
+                .iter()
+                .map(|pos| (pos.x, pos.y))
+                .collect()
+        };
+        assert_eq!(cells((2, 2), (2, 2)), vec![(2, 2)]);
+        assert_eq!(cells((0, 0), (3, 0)), vec![(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(cells((0, 3), (0, 0)), vec![(0, 2), (0, 1), (0, 0)]);
+        assert_eq!(cells((0, 0), (2, 2)), vec![(1, 1), (2, 2)]);
+        // A shallow line still steps one column at a time, without gaps.
+        let shallow = cells((0, 0), (4, 1));
+        assert_eq!(shallow.len(), 4);
+        assert_eq!(shallow.last(), Some(&(4, 1)));
+        for pair in shallow.windows(2) {
+            assert_eq!(pair[1].0 - pair[0].0, 1);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::grid::GridPosition;
+
+// Not every piece of food is the same. Each kind does something different to the
+// snake when it's eaten, and all but the normal kind only stick around for a while.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FoodKind {
+    // Grows the snake by one segment.
+    Normal,
+    // Grows the snake like normal food but is worth a lot more, if the snake gets
+    // to it before it disappears.
+    Bonus,
+    // Takes a couple of segments off the end of the snake.
+    Shrink,
+    // Makes the game run faster for a while...
+    SpeedUp,
+    // ...or slower.
+    SlowDown,
+    // Takes a chunk off the end of the snake and is worth nothing at all.
+    Poison,
+}
+
+impl FoodKind {
+    pub const ALL: [FoodKind; 6] = [
+        FoodKind::Normal,
+        FoodKind::Bonus,
+        FoodKind::Shrink,
+        FoodKind::SpeedUp,
+        FoodKind::SlowDown,
+        FoodKind::Poison,
+    ];
+
+    // How likely this kind is to be picked when a new piece of food is placed,
+    // relative to the others.
+    pub fn spawn_weight(&self) -> u32 {
+        match *self {
+            FoodKind::Normal => 60,
+            FoodKind::Bonus => 10,
+            FoodKind::Shrink => 8,
+            FoodKind::SpeedUp => 8,
+            FoodKind::SlowDown => 8,
+            FoodKind::Poison => 6,
+        }
+    }
+
+    // How many ticks this kind of food stays on the board before it disappears,
+    // or `None` if it stays until it's eaten.
+    pub fn lifetime(&self) -> Option<u32> {
+        match *self {
+            FoodKind::Normal => None,
+            FoodKind::Bonus => Some(40),
+            FoodKind::Shrink => Some(80),
+            FoodKind::SpeedUp | FoodKind::SlowDown => Some(60),
+            FoodKind::Poison => Some(100),
+        }
+    }
+
+    // How many segments the snake gains from eating this, or loses if it's negative.
+    pub fn growth(&self) -> i32 {
+        match *self {
+            FoodKind::Normal | FoodKind::Bonus | FoodKind::SpeedUp | FoodKind::SlowDown => 1,
+            FoodKind::Shrink => -2,
+            FoodKind::Poison => -3,
+        }
+    }
+
+    // How many times the usual points this is worth. Food worth nothing doesn't
+    // count towards the multiplier or the level either.
+    pub fn value(&self) -> u32 {
+        match *self {
+            FoodKind::Normal | FoodKind::Shrink | FoodKind::SlowDown => 1,
+            FoodKind::SpeedUp => 2,
+            FoodKind::Bonus => 5,
+            FoodKind::Poison => 0,
+        }
+    }
+
+    // How much eating this changes the speed of the game, in updates per second,
+    // and for how many ticks.
+    pub fn speed_effect(&self) -> Option<(f32, u32)> {
+        match *self {
+            FoodKind::SpeedUp => Some((3.0, 40)),
+            FoodKind::SlowDown => Some((-3.0, 40)),
+            _ => None,
+        }
+    }
+
+    // Picks a kind at random, with each kind as likely as its spawn weight says.
+    pub fn random<R: Rng>(rng: &mut R) -> Self {
+        let total: u32 = FoodKind::ALL.iter().map(|kind| kind.spawn_weight()).sum();
+        let mut pick = rng.gen_range(0..total);
+        for &kind in FoodKind::ALL.iter() {
+            if pick < kind.spawn_weight() {
+                return kind;
+            }
+            pick -= kind.spawn_weight();
+        }
+        FoodKind::Normal
+    }
+}
+
+// This is an abstraction over a `GridPosition` that represents
+// a piece of food the snake can eat. How it looks on screen is
+// entirely up to whichever frontend is drawing the game.
+#[derive(Clone, Copy, Debug)]
+pub struct Food {
+    pub pos: GridPosition,
+    pub kind: FoodKind,
+    // How many more ticks this food will stay on the board, if it doesn't stay forever.
+    pub ticks_left: Option<u32>,
+}
+
+impl Food {
+    pub fn new(pos: GridPosition) -> Self {
+        Food::with_kind(pos, FoodKind::Normal)
+    }
+
+    pub fn with_kind(pos: GridPosition, kind: FoodKind) -> Self {
+        Food {
+            pos,
+            kind,
+            ticks_left: kind.lifetime(),
+        }
+    }
+
+    // Counts down one tick of the food's lifetime, and returns whether it has
+    // now run out.
+    pub fn tick(&mut self) -> bool {
+        match &mut self.ticks_left {
+            Some(ticks) => {
+                *ticks = ticks.saturating_sub(1);
+                *ticks == 0
+            }
+            None => false,
+        }
+    }
+}
+
+// All the food on the board. We keep the pieces in a list, so that we always go
+// through them in the same order and a seed always replays the same game, and
+// alongside it an index from cell to place in the list, so that finding out
+// whether there's food in a given cell takes constant time.
+#[derive(Clone, Debug, Default)]
+pub struct FoodField {
+    items: Vec<Food>,
+    index: HashMap<GridPosition, usize>,
+}
+
+impl FoodField {
+    pub fn new() -> Self {
+        FoodField::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Food> {
+        self.items.iter()
+    }
+
+    // The food in the given cell, if there is any.
+    pub fn get(&self, pos: GridPosition) -> Option<&Food> {
+        self.index.get(&pos).map(|&i| &self.items[i])
+    }
+
+    pub fn contains(&self, pos: GridPosition) -> bool {
+        self.index.contains_key(&pos)
+    }
+
+    // Puts a piece of food on the board. Each cell holds at most one piece, so
+    // any food already in that cell is replaced.
+    pub fn insert(&mut self, food: Food) {
+        match self.index.get(&food.pos) {
+            Some(&i) => self.items[i] = food,
+            None => {
+                self.index.insert(food.pos, self.items.len());
+                self.items.push(food);
+            }
+        }
+    }
+
+    // Takes the food out of the given cell, if there is any. We move the last
+    // piece in the list into its place so we don't have to shift everything down.
+    pub fn remove(&mut self, pos: GridPosition) -> Option<Food> {
+        let i = self.index.remove(&pos)?;
+        let food = self.items.swap_remove(i);
+        if let Some(moved) = self.items.get(i) {
+            self.index.insert(moved.pos, i);
+        }
+        Some(food)
+    }
+
+    // Counts down one tick for every piece of food, and takes away any that have
+    // run out of time. We return the ones we took away, in the order they were in.
+    pub fn tick(&mut self) -> Vec<Food> {
+        let expired: Vec<GridPosition> = self
+            .items
+            .iter_mut()
+            .filter_map(|food| if food.tick() { Some(food.pos) } else { None })
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|pos| self.remove(pos))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(cells: &[(i16, i16)]) -> FoodField {
+        let mut field = FoodField::new();
+        for &pos in cells.iter() {
+            field.insert(Food::new(pos.into()));
+        }
+        field
+    }
+
+    #[test]
+    fn finds_food_that_was_moved_to_fill_a_gap() {
+        let mut field = field(&[(1, 1), (2, 2), (3, 3), (4, 4)]);
+        let removed = field.remove(GridPosition::new(2, 2)).unwrap();
+        assert_eq!(removed.pos, GridPosition::new(2, 2));
+        assert_eq!(field.len(), 3);
+        assert!(!field.contains(GridPosition::new(2, 2)));
+        // The last piece has moved into the gap, and can still be found and taken away.
+        let moved = GridPosition::new(4, 4);
+        assert_eq!(field.get(moved).map(|food| food.pos), Some(moved));
+        assert_eq!(field.remove(moved).map(|food| food.pos), Some(moved));
+        for &pos in [(1, 1), (3, 3)].iter() {
+            assert!(field.contains(pos.into()));
+        }
+        assert_eq!(field.remove(moved).map(|food| food.pos), None);
+        assert_eq!(field.len(), 2);
+    }
+
+    #[test]
+    fn removing_the_last_piece_moves_nothing() {
+        let mut field = field(&[(1, 1), (2, 2)]);
+        field.remove(GridPosition::new(2, 2));
+        assert_eq!(
+            field.get(GridPosition::new(1, 1)).map(|food| food.pos),
+            Some(GridPosition::new(1, 1))
+        );
+        field.remove(GridPosition::new(1, 1));
+        assert!(field.is_empty());
+    }
+
+    #[test]
+    fn putting_food_where_there_already_is_some_replaces_it() {
+        let mut field = field(&[(1, 1)]);
+        field.insert(Food::with_kind(GridPosition::new(1, 1), FoodKind::Bonus));
+        assert_eq!(field.len(), 1);
+        assert_eq!(
+            field.get(GridPosition::new(1, 1)).map(|food| food.kind),
+            Some(FoodKind::Bonus)
+        );
+    }
+
+    #[test]
+    fn takes_away_food_whose_time_is_up() {
+        let mut field = FoodField::new();
+        let soon = |pos: (i16, i16), ticks| Food {
+            ticks_left: Some(ticks),
+            ..Food::with_kind(pos.into(), FoodKind::Bonus)
+        };
+        field.insert(soon((1, 1), 2));
+        field.insert(Food::new(GridPosition::new(2, 2)));
+        field.insert(soon((3, 3), 1));
+        field.insert(soon((4, 4), 1));
+        field.insert(soon((5, 5), 3));
+        let expired: Vec<GridPosition> = field.tick().iter().map(|food| food.pos).collect();
+        assert_eq!(
+            expired,
+            vec![GridPosition::new(3, 3), GridPosition::new(4, 4)]
+        );
+        assert_eq!(field.len(), 3);
+        assert_eq!(
+            field
+                .get(GridPosition::new(5, 5))
+                .and_then(|food| food.ticks_left),
+            Some(2)
+        );
+        let expired: Vec<GridPosition> = field.tick().iter().map(|food| food.pos).collect();
+        assert_eq!(expired, vec![GridPosition::new(1, 1)]);
+        // Food that stays forever never goes.
+        for _ in 0..100 {
+            field.tick();
+        }
+        assert_eq!(field.len(), 1);
+        assert!(field.contains(GridPosition::new(2, 2)));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::grid::Direction;
+use crate::versus::MAX_PLAYERS;
+
+// How far an analog stick has to be pushed, from 0.0 in the middle to 1.0 all the
+// way over, before we take it to mean anything. Sticks rarely rest at exactly 0.0,
+// and we don't want a worn one steering the snake on its own.
+pub const DEFAULT_DEADZONE: f32 = 0.5;
+
+// The direction an analog stick is pushed in, if it's pushed past the deadzone.
+// `x` goes from -1.0 all the way left to 1.0 all the way right, and `y` from -1.0
+// all the way down to 1.0 all the way up. The snake can only go one way at a time,
+// so a stick pushed diagonally counts as whichever way it's pushed furthest, and
+// one pushed exactly diagonally as up or down.
+pub fn stick_direction(x: f32, y: f32, deadzone: f32) -> Option<Direction> {
+    if x.abs() < deadzone && y.abs() < deadzone {
+        None
+    } else if x.abs() > y.abs() {
+        Some(if x > 0.0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        })
+    } else {
+        Some(if y > 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        })
+    }
+}
+
+// An `AnalogStick` follows where a stick is pushed as its axes move one at a time,
+// and says when it's pushed a new way. Holding the stick still doesn't keep
+// turning the snake; to turn the same way again, the stick has to go back past the
+// deadzone first, just like letting go of a key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnalogStick {
+    x: f32,
+    y: f32,
+    deadzone: f32,
+    // Which way the stick was last pushed, if it's out of the deadzone.
+    direction: Option<Direction>,
+}
+
+impl AnalogStick {
+    pub fn new(deadzone: f32) -> Self {
+        AnalogStick {
+            x: 0.0,
+            y: 0.0,
+            deadzone,
+            direction: None,
+        }
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    // Moves the stick's horizontal axis, and returns the way it's now pushed if
+    // that's a new one.
+    pub fn set_x(&mut self, x: f32) -> Option<Direction> {
+        self.x = x;
+        self.update()
+    }
+
+    // Moves the vertical axis, where up is positive.
+    pub fn set_y(&mut self, y: f32) -> Option<Direction> {
+        self.y = y;
+        self.update()
+    }
+
+    fn update(&mut self) -> Option<Direction> {
+        let direction = stick_direction(self.x, self.y, self.deadzone);
+        let changed = direction != self.direction;
+        self.direction = direction;
+        if changed {
+            direction
+        } else {
+            None
+        }
+    }
+}
+
+// `Controllers` decides which player each controller belongs to. Controllers are
+// handed out in the order they're first used, so whoever picks one up and presses
+// something first is the first player. Once every player has one, any more are
+// ignored. `Id` is however the frontend tells controllers apart.
+#[derive(Clone, Debug)]
+pub struct Controllers<Id> {
+    deadzone: f32,
+    // Each player's controller, in player order, with the state of its stick.
+    players: Vec<(Id, AnalogStick)>,
+}
+
+impl<Id: Copy + PartialEq> Controllers<Id> {
+    pub fn new(deadzone: f32) -> Self {
+        Controllers {
+            deadzone,
+            players: Vec::new(),
+        }
+    }
+
+    // How many controllers have been handed out.
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    // The player a controller belongs to, handing it to the next player without one
+    // if it's new, or `None` if every player already has a controller.
+    pub fn player(&mut self, id: Id) -> Option<usize> {
+        match self.players.iter().position(|&(other, _)| other == id) {
+            Some(player) => Some(player),
+            None if self.players.len() < MAX_PLAYERS => {
+                self.players.push((id, AnalogStick::new(self.deadzone)));
+                Some(self.players.len() - 1)
+            }
+            None => None,
+        }
+    }
+
+    // The stick on a player's controller.
+    pub fn stick(&mut self, player: usize) -> Option<&mut AnalogStick> {
+        self.players.get_mut(player).map(|(_, stick)| stick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, BoardView};
+    use crate::controls::ControlMode;
+    use crate::food::FoodField;
+    use crate::grid::GridPosition;
+    use crate::snake::Snake;
+
+    #[test]
+    fn a_stick_inside_the_deadzone_does_nothing() {
+        assert_eq!(stick_direction(0.0, 0.0, 0.5), None);
+        assert_eq!(stick_direction(0.3, -0.4, 0.5), None);
+        assert_eq!(stick_direction(-0.49, 0.49, 0.5), None);
+    }
+
+    #[test]
+    fn each_axis_points_its_own_way() {
+        assert_eq!(stick_direction(1.0, 0.0, 0.5), Some(Direction::Right));
+        assert_eq!(stick_direction(-1.0, 0.0, 0.5), Some(Direction::Left));
+        // Up is positive, unlike rows on the board.
+        assert_eq!(stick_direction(0.0, 1.0, 0.5), Some(Direction::Up));
+        assert_eq!(stick_direction(0.0, -1.0, 0.5), Some(Direction::Down));
+        // Right on the edge of the deadzone counts.
+        assert_eq!(stick_direction(0.5, 0.0, 0.5), Some(Direction::Right));
+    }
+
+    #[test]
+    fn a_diagonal_goes_whichever_way_is_pushed_furthest() {
+        assert_eq!(stick_direction(0.9, 0.6, 0.5), Some(Direction::Right));
+        assert_eq!(stick_direction(-0.6, -0.9, 0.5), Some(Direction::Down));
+        // Only one axis has to be out of the deadzone.
+        assert_eq!(stick_direction(-0.7, 0.4, 0.5), Some(Direction::Left));
+        // Exactly diagonal counts as up or down.
+        assert_eq!(stick_direction(0.7, 0.7, 0.5), Some(Direction::Up));
+        assert_eq!(stick_direction(0.7, -0.7, 0.5), Some(Direction::Down));
+    }
+
+    #[test]
+    fn holding_the_stick_only_turns_once() {
+        let mut stick = AnalogStick::new(0.5);
+        assert_eq!(stick.set_x(0.8), Some(Direction::Right));
+        // Wobbling while held the same way doesn't turn again...
+        assert_eq!(stick.set_x(0.9), None);
+        assert_eq!(stick.set_y(0.2), None);
+        assert_eq!(stick.direction(), Some(Direction::Right));
+        // ...but swinging round to another way does.
+        assert_eq!(stick.set_y(0.95), Some(Direction::Up));
+        // Going back to the middle turns nothing, and then pushing the same way
+        // again turns again.
+        assert_eq!(stick.set_x(0.0), None);
+        assert_eq!(stick.set_y(0.0), None);
+        assert_eq!(stick.direction(), None);
+        assert_eq!(stick.set_y(0.7), Some(Direction::Up));
+    }
+
+    #[test]
+    fn controllers_go_to_players_in_the_order_theyre_used() {
+        let mut controllers = Controllers::new(DEFAULT_DEADZONE);
+        assert_eq!(controllers.player(7), Some(0));
+        assert_eq!(controllers.player(3), Some(1));
+        assert_eq!(controllers.player(7), Some(0));
+        // Once everyone has a controller, any more are ignored.
+        assert_eq!(controllers.player(9), None);
+        assert_eq!(controllers.len(), MAX_PLAYERS);
+    }
+
+    // The stick and the D-pad go through the same steering as the keys, so in
+    // relative mode pushing the stick right turns the snake right from wherever
+    // it's heading, rather than making it face right.
+    #[test]
+    fn the_stick_steers_relative_too() {
+        let board = Board::new(10, 10);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(5, 5), Direction::Up, &board);
+        let mut stick = AnalogStick::new(DEFAULT_DEADZONE);
+        let push = |snake: &mut Snake, dir: Option<Direction>| {
+            if let Some(steer) = dir.and_then(|dir| ControlMode::Relative.steer(dir)) {
+                snake.steer(steer);
+            }
+            snake.update(&BoardView::new(&board, &food));
+        };
+        push(&mut snake, stick.set_x(0.9));
+        assert_eq!(snake.last_update_dir(), Direction::Right);
+        // Pushing it up doesn't do anything in relative mode...
+        push(&mut snake, stick.set_y(1.0));
+        assert_eq!(snake.last_update_dir(), Direction::Right);
+        // ...and letting go and pushing right again turns right again.
+        stick.set_x(0.0);
+        stick.set_y(0.0);
+        push(&mut snake, stick.set_x(0.9));
+        assert_eq!(snake.last_update_dir(), Direction::Down);
+        assert_eq!(snake.head().pos, GridPosition::new(7, 6));
+    }
+}
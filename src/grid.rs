@@ -0,0 +1,198 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::Board;
+
+// Now we define a struct that will hold an entity's position on our game board
+// or grid. We'll use signed integers because we only want
+// to store whole numbers, and we need them to be signed so that they work properly
+// with our modulus arithmetic later.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct GridPosition {
+    pub x: i16,
+    pub y: i16,
+}
+
+// This is a trait that provides a modulus function that works for negative values
+// rather than just the standard remainder op (%) which does not. We'll use this
+// to get our snake to wrap from one side of the game board around to the other
+// when it goes off the top, bottom, left, or right side of the screen.
+trait ModuloSigned {
+    fn modulo(&self, n: Self) -> Self;
+}
+
+// Here we implement our `ModuloSigned` trait for any type T which implements
+// `Add` (the `+` operator) with an output type T and Rem (the `%` operator)
+// that also has anout put type of T, and that can be cloned. These are the bounds
+// that we need in order to implement a modulus function that works for negative numbers
+// as well.
+impl<T> ModuloSigned for T
+where
+    T: std::ops::Add<Output = T> + std::ops::Rem<Output = T> + Clone,
+{
+    fn modulo(&self, n: T) -> T {
+        // Because of our trait bounds, we can now apply these operators.
+        (self.clone() % n.clone() + n.clone()) % n
+    }
+}
+
+impl GridPosition {
+    // We make a standard helper function so that we can create a new `GridPosition`
+    // more easily.
+    pub fn new(x: i16, y: i16) -> Self {
+        GridPosition { x, y }
+    }
+
+    // We'll make another helper function that takes one grid position and returns a new one after
+    // making one move in the direction of `dir`. We use our `SignedModulo` trait
+    // above, which is now implemented on `i16` because it satisfies the trait bounds,
+    // to automatically wrap around within the board's size if the move would have otherwise
+    // moved us off the board to the top, bottom, left, or right.
+    pub fn new_from_move(pos: GridPosition, dir: Direction, board: &Board) -> Self {
+        match dir {
+            Direction::Up => GridPosition::new(pos.x, (pos.y - 1).modulo(board.height)),
+            Direction::Down => GridPosition::new(pos.x, (pos.y + 1).modulo(board.height)),
+            Direction::Left => GridPosition::new((pos.x - 1).modulo(board.width), pos.y),
+            Direction::Right => GridPosition::new((pos.x + 1).modulo(board.width), pos.y),
+        }
+    }
+}
+
+// And here we implement `From` to allow us to easily convert between
+// `(i16, i16)` and a `GridPosition`.
+impl From<(i16, i16)> for GridPosition {
+    fn from(pos: (i16, i16)) -> Self {
+        GridPosition { x: pos.0, y: pos.1 }
+    }
+}
+
+// Next we create an enum that will represent all the possible
+// directions that our snake could move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    // We create a helper function that will allow us to easily get the inverse
+    // of a `Direction` which we can use later to check if the player should be
+    // able to move the snake in a certain direction.
+    pub fn inverse(&self) -> Self {
+        match *self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    // These give us the direction we'd be facing after a quarter turn to
+    // the right or to the left.
+    pub fn clockwise(&self) -> Self {
+        match *self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    pub fn counter_clockwise(&self) -> Self {
+        self.clockwise().inverse()
+    }
+
+    // The direction we'd be facing after a quarter turn either way.
+    pub fn rotated(&self, rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Clockwise => self.clockwise(),
+            Rotation::CounterClockwise => self.counter_clockwise(),
+        }
+    }
+
+    // How far one move in this direction takes us along each axis. Remember
+    // that `y` grows as we go down the screen.
+    pub fn delta(&self) -> (i16, i16) {
+        match *self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+}
+
+// A quarter turn, which way round depending on how you look at it: clockwise is a
+// right turn from wherever the snake is heading, and counter-clockwise a left one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Direction::ALL
+            .iter()
+            .copied()
+            .find(|dir| dir.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown direction {:?}; expected up, down, left or right",
+                    name
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_a_quarter_at_a_time() {
+        assert_eq!(Direction::Up.clockwise(), Direction::Right);
+        assert_eq!(Direction::Right.clockwise(), Direction::Down);
+        assert_eq!(Direction::Down.clockwise(), Direction::Left);
+        assert_eq!(Direction::Left.clockwise(), Direction::Up);
+        assert_eq!(Direction::Up.counter_clockwise(), Direction::Left);
+        assert_eq!(Direction::Left.counter_clockwise(), Direction::Down);
+        for &dir in Direction::ALL.iter() {
+            assert_eq!(dir.rotated(Rotation::Clockwise), dir.clockwise());
+            assert_eq!(
+                dir.rotated(Rotation::CounterClockwise),
+                dir.counter_clockwise()
+            );
+            // A turn one way and then the other gets us back where we started,
+            // and two turns the same way face us the other way.
+            assert_eq!(dir.clockwise().counter_clockwise(), dir);
+            assert_eq!(dir.clockwise().clockwise(), dir.inverse());
+            assert_eq!(dir.counter_clockwise().counter_clockwise(), dir.inverse());
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+
+use serde::{Deserialize, Serialize};
+
+// How many scores we keep in the table.
+pub const HIGH_SCORE_COUNT: usize = 10;
+
+// A single entry in the high score table, with enough information about the
+// game to brag about it and to replay it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    pub length: u32,
+    // How long the game lasted, in seconds.
+    pub duration_secs: u64,
+    // TOML can only store signed 64-bit integers, and a seed can be any `u64`,
+    // so we write the seed out as a string.
+    #[serde(with = "seed_string")]
+    pub seed: u64,
+    // When the game was played, in seconds since the Unix epoch.
+    pub date: u64,
+}
+
+// The high score table keeps the best `HIGH_SCORE_COUNT` scores, best first.
+// It knows how to read and write itself as TOML, but where that TOML ends up
+// is up to the frontend.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighScoreTable {
+    #[serde(default)]
+    scores: Vec<HighScore>,
+}
+
+impl HighScoreTable {
+    pub fn new() -> Self {
+        HighScoreTable::default()
+    }
+
+    // Reads a table back from TOML. Whatever we read, we put it back in order
+    // and trim it to size, in case the file was edited by hand.
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        let mut table: HighScoreTable = toml::from_str(text)?;
+        table.scores.sort_by_key(|entry| Reverse(entry.score));
+        table.scores.truncate(HIGH_SCORE_COUNT);
+        Ok(table)
+    }
+
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    pub fn entries(&self) -> &[HighScore] {
+        &self.scores
+    }
+
+    // The best score in the table, or 0 if nobody has played yet.
+    pub fn best(&self) -> u32 {
+        self.scores.first().map_or(0, |entry| entry.score)
+    }
+
+    // Whether a game with this score would make it into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.scores.len() < HIGH_SCORE_COUNT
+                || self.scores.last().is_none_or(|last| score > last.score))
+    }
+
+    // Adds an entry to the table and returns where it ended up, or `None` if it
+    // wasn't good enough. Ties go to whoever got the score first.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+        let rank = self
+            .scores
+            .iter()
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or(self.scores.len());
+        self.scores.insert(rank, entry);
+        self.scores.truncate(HIGH_SCORE_COUNT);
+        Some(rank)
+    }
+}
+
+// Turns a date stored as seconds since the Unix epoch into a `YYYY-MM-DD` string.
+// This uses the days-to-civil algorithm from Howard Hinnant's date library so we
+// don't have to pull in a whole date crate just to print a date.
+pub fn format_date(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// The (de)serializers we use to store a seed as a string.
+mod seed_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&seed.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            score,
+            length: 2,
+            duration_secs: 60,
+            seed: 1,
+            date: 0,
+        }
+    }
+
+    // A full table, scoring 100, 90, ... down to 10.
+    fn full_table() -> HighScoreTable {
+        let mut table = HighScoreTable::new();
+        for i in 0..HIGH_SCORE_COUNT as u32 {
+            table.insert(entry(&format!("p{}", i), 100 - i * 10));
+        }
+        table
+    }
+
+    fn scores(table: &HighScoreTable) -> Vec<u32> {
+        table.entries().iter().map(|entry| entry.score).collect()
+    }
+
+    #[test]
+    fn any_score_qualifies_until_the_table_is_full() {
+        let table = HighScoreTable::new();
+        assert!(table.qualifies(1));
+        // Nothing is not a score worth keeping.
+        assert!(!table.qualifies(0));
+        let table = full_table();
+        assert!(table.qualifies(11));
+        // Tying the lowest score isn't enough to knock it out.
+        assert!(!table.qualifies(10));
+        assert!(!table.qualifies(5));
+    }
+
+    #[test]
+    fn keeps_the_best_scores_best_first() {
+        let mut table = HighScoreTable::new();
+        assert_eq!(table.insert(entry("a", 30)), Some(0));
+        assert_eq!(table.insert(entry("b", 50)), Some(0));
+        assert_eq!(table.insert(entry("c", 40)), Some(1));
+        assert_eq!(scores(&table), vec![50, 40, 30]);
+        assert_eq!(table.best(), 50);
+    }
+
+    #[test]
+    fn drops_whatever_falls_off_the_bottom() {
+        let mut table = full_table();
+        assert_eq!(table.insert(entry("new", 55)), Some(5));
+        assert_eq!(table.entries().len(), HIGH_SCORE_COUNT);
+        assert_eq!(
+            scores(&table),
+            vec![100, 90, 80, 70, 60, 55, 50, 40, 30, 20]
+        );
+        assert_eq!(table.insert(entry("late", 20)), None);
+        assert_eq!(table.entries().len(), HIGH_SCORE_COUNT);
+    }
+
+    #[test]
+    fn ties_go_to_whoever_got_there_first() {
+        let mut table = HighScoreTable::new();
+        table.insert(entry("first", 40));
+        assert_eq!(table.insert(entry("second", 40)), Some(1));
+        let names: Vec<&str> = table.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let mut table = full_table();
+        table.insert(HighScore {
+            seed: u64::MAX,
+            ..entry("big seed", 95)
+        });
+        let text = table.to_toml().unwrap();
+        assert_eq!(HighScoreTable::from_toml(&text).unwrap(), table);
+    }
+
+    #[test]
+    fn sorts_and_trims_a_hand_edited_file() {
+        let mut text = String::new();
+        for score in [5, 50, 20, 1, 2, 3, 4, 6, 7, 8, 9, 10].iter() {
+            text.push_str(&format!(
+                "[[scores]]\nname = \"x\"\nscore = {}\nlength = 2\nduration_secs = 1\nseed = \"7\"\ndate = 0\n\n",
+                score
+            ));
+        }
+        let table = HighScoreTable::from_toml(&text).unwrap();
+        assert_eq!(scores(&table), vec![50, 20, 10, 9, 8, 7, 6, 5, 4, 3]);
+    }
+
+    #[test]
+    fn refuses_a_malformed_file() {
+        assert!(HighScoreTable::from_toml("[[scores]]\nname = \"x\"\n").is_err());
+        assert!(HighScoreTable::from_toml("scores = 3").is_err());
+        assert!(HighScoreTable::from_toml("[[scores]\n").is_err());
+        let bad_seed = "[[scores]]\nname = \"x\"\nscore = 1\nlength = 2\nduration_secs = 1\nseed = \"minus one\"\ndate = 0\n";
+        assert!(HighScoreTable::from_toml(bad_seed).is_err());
+        // An empty file is just an empty table.
+        assert_eq!(
+            HighScoreTable::from_toml("").unwrap(),
+            HighScoreTable::new()
+        );
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+    }
+}
@@ -0,0 +1,424 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, EdgePolicy};
+use crate::difficulty::Difficulty;
+use crate::grid::{Direction, GridPosition};
+
+// A `Level` is everything needed to set up a game before the first tick: the
+// board with its obstacles, portals and edges, where the snake starts out and which
+// way it's facing, any food that's waiting for it, and what it takes to win.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub name: String,
+    pub board: Board,
+    pub start: GridPosition,
+    pub start_dir: Direction,
+    // Food that's on the board from the very start, in these cells. Once it's
+    // eaten, new food turns up at random like it always does.
+    pub food: Vec<GridPosition>,
+    // How fast the level starts out, in updates per second, if it doesn't leave
+    // that up to the difficulty.
+    pub speed: Option<f32>,
+    pub win: WinCondition,
+}
+
+impl Level {
+    // An empty board, with the snake a quarter of the way across and half way down,
+    // heading right, like the original game.
+    pub fn open(board: Board) -> Self {
+        let start = GridPosition::new(board.width / 4, board.height / 2);
+        Level {
+            name: BuiltinLevel::Open.to_string(),
+            board,
+            start,
+            start_dir: Direction::Right,
+            food: Vec::new(),
+            speed: None,
+            win: WinCondition::default(),
+        }
+    }
+
+    // The speed curve to play this level with: the one we're given, but starting
+    // at the level's own speed if it has one.
+    pub fn difficulty(&self, difficulty: Difficulty) -> Difficulty {
+        match self.speed {
+            Some(speed) => Difficulty {
+                start_speed: speed,
+                max_speed: difficulty.max_speed.max(speed),
+                ..difficulty
+            },
+            None => difficulty,
+        }
+    }
+
+    // The cell right behind the snake's head when it starts out.
+    pub fn start_tail(&self) -> GridPosition {
+        GridPosition::new_from_move(self.start, self.start_dir.inverse(), &self.board)
+    }
+
+    // Checks that the snake has somewhere to start: both its head and the segment
+    // behind it have to be on the board and clear of obstacles and portals, and the
+    // segment can't have wrapped around from the other side of a board with walls.
+    pub fn validate(&self) -> Result<(), String> {
+        let tail = self.start_tail();
+        for &(what, pos) in [("start", self.start), ("tail", tail)].iter() {
+            if !self.board.contains(pos) {
+                return Err(format!(
+                    "the snake's {} ({}, {}) is off the board",
+                    what, pos.x, pos.y
+                ));
+            }
+            if self.board.is_obstacle(pos) {
+                return Err(format!(
+                    "the snake's {} ({}, {}) is inside an obstacle",
+                    what, pos.x, pos.y
+                ));
+            }
+            if self.board.is_portal(pos) {
+                return Err(format!(
+                    "the snake's {} ({}, {}) is on a portal",
+                    what, pos.x, pos.y
+                ));
+            }
+        }
+        if self.board.step(self.start, self.start_dir.inverse()) != Some(tail) {
+            return Err("the snake's tail would start off the edge of the board".to_string());
+        }
+        Ok(())
+    }
+
+    // Where each snake starts out, and which way it's facing, when `players`
+    // snakes play on this level at once. The first snake starts at the level's own
+    // start. The others start at its mirror images: the second on the opposite
+    // side of the board, heading the other way, the third mirrored left to right
+    // and the fourth mirrored top to bottom. If a mirror image lands somewhere a
+    // snake can't start, like inside a wall, we take the next cell along where one
+    // can. On a board too crowded to fit everyone, we return fewer starts than
+    // were asked for.
+    pub fn starts(&self, players: usize) -> Vec<(GridPosition, Direction)> {
+        let mut starts = vec![(self.start, self.start_dir)];
+        let mut taken = vec![self.start, self.start_tail()];
+        let (width, height) = (self.board.width, self.board.height);
+        for player in 1..players {
+            let (x, y, dir) = (self.start.x, self.start.y, self.start_dir);
+            let (pos, dir) = match player % 4 {
+                1 => (
+                    GridPosition::new(width - 1 - x, height - 1 - y),
+                    dir.inverse(),
+                ),
+                2 => (GridPosition::new(width - 1 - x, y), mirror(dir, true)),
+                3 => (GridPosition::new(x, height - 1 - y), mirror(dir, false)),
+                _ => (self.start, dir),
+            };
+            // We look for somewhere to start from the mirror image onwards, going
+            // along the rows and round to the top again, trying the mirrored
+            // direction before any other.
+            let cells = self.board.cell_count() as i32;
+            let first = pos.y as i32 * width as i32 + pos.x as i32;
+            let found = (0..cells)
+                .map(|i| {
+                    let index = (first + i).rem_euclid(cells);
+                    GridPosition::new((index % width as i32) as i16, (index / width as i32) as i16)
+                })
+                .flat_map(|pos| {
+                    std::iter::once(dir)
+                        .chain(Direction::ALL.iter().copied().filter(move |&d| d != dir))
+                        .map(move |dir| (pos, dir))
+                })
+                .find(|&(pos, dir)| self.fits_snake(pos, dir, &taken));
+            match found {
+                Some((pos, dir)) => {
+                    starts.push((pos, dir));
+                    taken.push(pos);
+                    taken.push(GridPosition::new_from_move(pos, dir.inverse(), &self.board));
+                }
+                None => break,
+            }
+        }
+        starts
+    }
+
+    // Whether a new snake could start at `pos` heading `dir`: both of its cells, and
+    // the cell in front of it, have to be on the board, clear of obstacles and
+    // portals, and not `taken` already.
+    fn fits_snake(&self, pos: GridPosition, dir: Direction, taken: &[GridPosition]) -> bool {
+        let tail = GridPosition::new_from_move(pos, dir.inverse(), &self.board);
+        let ahead = GridPosition::new_from_move(pos, dir, &self.board);
+        self.board.step(pos, dir.inverse()) == Some(tail)
+            && self.board.step(pos, dir) == Some(ahead)
+            && [pos, tail, ahead].iter().all(|&cell| {
+                self.board.contains(cell)
+                    && !self.board.is_obstacle(cell)
+                    && !self.board.is_portal(cell)
+                    && !taken.contains(&cell)
+            })
+    }
+
+    // Clears the obstacles out of the snake's way at the start: the cells it starts
+    // in, and a few cells ahead so the player has a moment to get their bearings.
+    fn clear_start(mut self) -> Self {
+        let mut pos = self.start_tail();
+        self.board.set_obstacle(pos, false);
+        pos = self.start;
+        for _ in 0..START_RUNWAY {
+            self.board.set_obstacle(pos, false);
+            match self.board.step(pos, self.start_dir) {
+                Some(next) => pos = next,
+                None => break,
+            }
+        }
+        self
+    }
+}
+
+// What the player has to do to win a level. Filling the whole board always wins,
+// whatever else the level asks for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WinCondition {
+    // Fill the board, like the original game.
+    #[default]
+    Fill,
+    // Score this many points.
+    Score(u32),
+    // Grow the snake to this many segments, counting the head.
+    Length(usize),
+    // Eat this many pieces of food.
+    Food(u32),
+}
+
+impl fmt::Display for WinCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinCondition::Fill => write!(f, "fill"),
+            WinCondition::Score(points) => write!(f, "score {}", points),
+            WinCondition::Length(length) => write!(f, "length {}", length),
+            WinCondition::Food(count) => write!(f, "food {}", count),
+        }
+    }
+}
+
+// A win condition is written as its name followed by how much of it it takes,
+// like `score 100`, apart from `fill`, which is just the name.
+impl FromStr for WinCondition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut words = text.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let amount = words.next();
+        if words.next().is_some() {
+            return Err(format!("expected a win condition, not {:?}", text));
+        }
+        let parse_amount = || -> Result<u32, String> {
+            let amount =
+                amount.ok_or_else(|| format!("{} needs an amount, like `{} 10`", name, name))?;
+            match amount.parse() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(format!(
+                    "{} needs a positive whole number, not {:?}",
+                    name, amount
+                )),
+            }
+        };
+        match name {
+            "fill" if amount.is_none() => Ok(WinCondition::Fill),
+            "fill" => Err("fill doesn't take an amount".to_string()),
+            "score" => parse_amount().map(WinCondition::Score),
+            "length" => parse_amount().map(|n| WinCondition::Length(n as usize)),
+            "food" => parse_amount().map(WinCondition::Food),
+            _ => Err(format!(
+                "unknown win condition {:?}; expected fill, score, length or food",
+                name
+            )),
+        }
+    }
+}
+
+// The direction we'd be facing in a mirror, flipping left and right if
+// `horizontal` is set, or up and down if it isn't.
+fn mirror(dir: Direction, horizontal: bool) -> Direction {
+    match (dir, horizontal) {
+        (Direction::Left, true)
+        | (Direction::Right, true)
+        | (Direction::Up, false)
+        | (Direction::Down, false) => dir.inverse(),
+        _ => dir,
+    }
+}
+
+// How many cells we keep clear in front of the snake's head at the start of a
+// built-in level, counting the head itself.
+const START_RUNWAY: usize = 4;
+
+// The levels that come with the game. Each one is laid out to fit whatever size
+// of board it's played on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuiltinLevel {
+    // Nothing on the board at all.
+    #[default]
+    Open,
+    // A wall all the way around the edge.
+    Box,
+    // A plus sign in the middle of the board.
+    Cross,
+    // Walls winding in towards the middle, on a board with solid edges.
+    Spiral,
+    // Short walls dotted all over the board.
+    Maze,
+}
+
+impl BuiltinLevel {
+    pub const ALL: [BuiltinLevel; 5] = [
+        BuiltinLevel::Open,
+        BuiltinLevel::Box,
+        BuiltinLevel::Cross,
+        BuiltinLevel::Spiral,
+        BuiltinLevel::Maze,
+    ];
+
+    // Lays the level out on a board of the given size. Only the open level uses
+    // the edge policy it's given; every other level has its own.
+    pub fn level(&self, width: i16, height: i16, edges: EdgePolicy) -> Level {
+        let empty = Board::new(width, height);
+        let mut level = match *self {
+            BuiltinLevel::Open => return Level::open(empty.with_edges(edges)),
+            BuiltinLevel::Box => Level {
+                board: empty.with_obstacles(box_walls(width, height)),
+                ..Level::open(Board::new(width, height))
+            },
+            BuiltinLevel::Cross => Level {
+                board: empty.with_obstacles(cross_walls(width, height)),
+                start: GridPosition::new(width / 4, height / 4),
+                ..Level::open(Board::new(width, height))
+            },
+            BuiltinLevel::Spiral => Level {
+                board: empty
+                    .with_edges(EdgePolicy::Solid)
+                    .with_obstacles(spiral_walls(width, height)),
+                start: GridPosition::new((width / 4).max(1), 0),
+                ..Level::open(Board::new(width, height))
+            },
+            BuiltinLevel::Maze => Level {
+                board: empty.with_obstacles(maze_walls(width, height)),
+                ..Level::open(Board::new(width, height))
+            },
+        };
+        level.name = self.to_string();
+        level.clear_start()
+    }
+}
+
+impl fmt::Display for BuiltinLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BuiltinLevel::Open => "open",
+            BuiltinLevel::Box => "box",
+            BuiltinLevel::Cross => "cross",
+            BuiltinLevel::Spiral => "spiral",
+            BuiltinLevel::Maze => "maze",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for BuiltinLevel {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        BuiltinLevel::ALL
+            .iter()
+            .copied()
+            .find(|level| level.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown level {:?}; expected open, box, cross, spiral or maze",
+                    name
+                )
+            })
+    }
+}
+
+// Every cell around the edge of the board.
+fn box_walls(width: i16, height: i16) -> Vec<GridPosition> {
+    let mut walls = Vec::new();
+    for x in 0..width {
+        walls.push(GridPosition::new(x, 0));
+        walls.push(GridPosition::new(x, height - 1));
+    }
+    for y in 1..height - 1 {
+        walls.push(GridPosition::new(0, y));
+        walls.push(GridPosition::new(width - 1, y));
+    }
+    walls
+}
+
+// A bar across the middle half of the board each way, leaving the quarter of the
+// board around the edge clear.
+fn cross_walls(width: i16, height: i16) -> Vec<GridPosition> {
+    let horizontal = (width / 4 + 1..width - width / 4).map(|x| GridPosition::new(x, height / 2));
+    let vertical = (height / 4 + 1..height - height / 4).map(|y| GridPosition::new(width / 2, y));
+    horizontal.chain(vertical).collect()
+}
+
+// We draw the spiral like a turtle, going round the board clockwise and turning
+// in by three cells every time we come back round: one for the wall and two for
+// the corridor between one lap of wall and the next.
+fn spiral_walls(width: i16, height: i16) -> Vec<GridPosition> {
+    let mut walls = Vec::new();
+    let (mut left, mut top, mut right, mut bottom) = (2, 2, width - 3, height - 3);
+    while left <= right && top <= bottom {
+        walls.extend((left..=right).map(|x| GridPosition::new(x, top)));
+        walls.extend((top..=bottom).map(|y| GridPosition::new(right, y)));
+        walls.extend((left..=right).map(|x| GridPosition::new(x, bottom)));
+        // The left side stops short of the top, leaving the way in to the next lap.
+        walls.extend((top + 3..=bottom).map(|y| GridPosition::new(left, y)));
+        left += 3;
+        top += 3;
+        right -= 3;
+        bottom -= 3;
+    }
+    walls
+}
+
+// A pillar every four cells in each direction, each with a short wall sticking
+// out of it. Which way the wall sticks out goes round in turn from one pillar to
+// the next, which is enough to make the board feel like a maze without ever
+// shutting any part of it off.
+fn maze_walls(width: i16, height: i16) -> Vec<GridPosition> {
+    let mut walls = Vec::new();
+    for y in (2..height - 1).step_by(4) {
+        for x in (2..width - 1).step_by(4) {
+            let pillar = GridPosition::new(x, y);
+            let dir = Direction::ALL[((x / 4 + y / 4) % 4) as usize];
+            let (dx, dy) = dir.delta();
+            walls.push(pillar);
+            walls.push(GridPosition::new(x + dx, y + dy));
+        }
+    }
+    walls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spiral_corridors_are_two_cells_wide() {
+        let walls = spiral_walls(14, 14);
+        let mut rows: Vec<i16> = walls
+            .iter()
+            .filter(|pos| pos.x == 7)
+            .map(|pos| pos.y)
+            .collect();
+        rows.sort_unstable();
+        assert_eq!(rows, vec![2, 5, 8, 11]);
+        // The way in to the inner lap is a gap as wide as the corridor.
+        assert!(!walls.contains(&GridPosition::new(5, 6)));
+        assert!(!walls.contains(&GridPosition::new(5, 7)));
+        assert!(walls.contains(&GridPosition::new(5, 8)));
+    }
+}
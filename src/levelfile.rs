@@ -0,0 +1,857 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::board::{Board, EdgePolicy};
+use crate::config::{MAX_BOARD_SIZE, MIN_BOARD_SIZE};
+use crate::grid::{Direction, GridPosition};
+use crate::level::{Level, WinCondition};
+
+// Levels can be written by hand as plain text. A level file starts with a header of
+// `key: value` lines, then a line with just `---` on it, then the map itself, one
+// line per row of the board:
+//
+//     name: Two Rooms
+//     speed: 10
+//     edges: solid
+//     win: score 200
+//     ---
+//     ##########
+//     #>..#....#
+//     #...O..F.#
+//     #O..#....#
+//     ##########
+//
+// On the map, `#` is a wall, `F` is a piece of food that's there from the start,
+// `.` is an empty cell and `O` is a portal. The snake starts on an arrow, `^`, `>`,
+// `v` or `<`, heading the way it points, so that the start still fits in one
+// character. It can also start on an `S`, which doesn't say which way it's
+// heading: that's up to the `direction` key in the header, which older level
+// files use. A file can have both, as long as they agree. Portals are joined up
+// in pairs in the order they appear, reading each row left to right, so the first
+// and second `O` lead into each other, then the third and fourth, and so on.
+//
+// Every key in the header is optional, and so is the header itself: a file with
+// no `---` line is all map. Blank lines and lines starting with `#` in the header
+// are skipped. The snake heads right unless its start says otherwise, the edges
+// wrap around unless `edges` says otherwise, and the player wins by filling the
+// board unless `win` says otherwise.
+pub const HEADER_END: &str = "---";
+
+// What a level is called if its header doesn't give it a name.
+pub const UNTITLED: &str = "untitled";
+
+// Something wrong with a level file, and where in the file it is. Lines and
+// columns both count from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: LevelErrorKind,
+}
+
+// The ways a level file can be wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LevelErrorKind {
+    // A header line that isn't `key: value`.
+    NotKeyValue,
+    UnknownKey(String),
+    DuplicateKey(String),
+    // A header value we couldn't make sense of.
+    InvalidValue { key: String, message: String },
+    // There's no map after the header.
+    EmptyMap,
+    UnknownTile(char),
+    // A row that isn't as wide as the first one.
+    RaggedRow { expected: usize, found: usize },
+    // The map is too big or too small to play on.
+    BoardSize { width: usize, height: usize },
+    NoStart,
+    // A second start, after the first one.
+    ExtraStart,
+    // A start arrow pointing some other way than the `direction` in the header.
+    StartDirection { header: Direction, map: Direction },
+    // The snake can't start where the `S` is, for this reason.
+    BlockedStart(String),
+    // A piece of food the snake could never get to.
+    UnreachableFood,
+    // An empty cell the snake could never get to. Food would turn up there sooner
+    // or later and never be eaten, and the board could never be filled.
+    UnreachableCell,
+    // A portal without another one to pair up with.
+    UnpairedPortal,
+}
+
+impl fmt::Display for LevelErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelErrorKind::NotKeyValue => write!(f, "expected `key: value` or `{}`", HEADER_END),
+            LevelErrorKind::UnknownKey(key) => write!(
+                f,
+                "unknown key {:?}; expected name, speed, edges, win or direction",
+                key
+            ),
+            LevelErrorKind::DuplicateKey(key) => write!(f, "{} is given more than once", key),
+            LevelErrorKind::InvalidValue { key, message } => {
+                write!(f, "invalid {}: {}", key, message)
+            }
+            LevelErrorKind::EmptyMap => write!(f, "the level has no map"),
+            LevelErrorKind::UnknownTile(tile) => write!(
+                f,
+                "unknown tile {:?}; expected '#', '^', '>', 'v', '<', 'S', 'F', '.' or 'O'",
+                tile
+            ),
+            LevelErrorKind::RaggedRow { expected, found } => write!(
+                f,
+                "this row is {} cells wide, but the first row is {}",
+                found, expected
+            ),
+            LevelErrorKind::BoardSize { width, height } => write!(
+                f,
+                "the map must be between {} and {} cells on each side, not {}x{}",
+                MIN_BOARD_SIZE, MAX_BOARD_SIZE, width, height
+            ),
+            LevelErrorKind::NoStart => write!(
+                f,
+                "the map has no '^', '>', 'v', '<' or 'S' for the snake to start on"
+            ),
+            LevelErrorKind::ExtraStart => write!(f, "the map has more than one start"),
+            LevelErrorKind::StartDirection { header, map } => write!(
+                f,
+                "the start points {}, but the header says the snake heads {}",
+                map, header
+            ),
+            LevelErrorKind::BlockedStart(reason) => write!(f, "{}", reason),
+            LevelErrorKind::UnreachableFood => {
+                write!(f, "this food can't be reached from where the snake starts")
+            }
+            LevelErrorKind::UnreachableCell => write!(
+                f,
+                "this cell can't be reached from where the snake starts; wall it off"
+            ),
+            LevelErrorKind::UnpairedPortal => {
+                write!(f, "this portal has no other portal to pair up with")
+            }
+        }
+    }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl LevelError {
+    fn new(line: usize, column: usize, kind: LevelErrorKind) -> Self {
+        LevelError { line, column, kind }
+    }
+}
+
+// The tiles a map is drawn with, and the character each one is written as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapTile {
+    Empty,
+    Wall,
+    Start,
+    Food,
+    Portal,
+}
+
+impl MapTile {
+    pub const ALL: [MapTile; 5] = [
+        MapTile::Empty,
+        MapTile::Wall,
+        MapTile::Start,
+        MapTile::Food,
+        MapTile::Portal,
+    ];
+
+    pub fn symbol(&self) -> char {
+        match *self {
+            MapTile::Empty => '.',
+            MapTile::Wall => '#',
+            MapTile::Start => 'S',
+            MapTile::Food => 'F',
+            MapTile::Portal => 'O',
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        MapTile::ALL
+            .iter()
+            .copied()
+            .find(|tile| tile.symbol() == symbol)
+    }
+}
+
+// The arrow a start is written as, heading the way it points.
+pub fn start_symbol(dir: Direction) -> char {
+    match dir {
+        Direction::Up => '^',
+        Direction::Right => '>',
+        Direction::Down => 'v',
+        Direction::Left => '<',
+    }
+}
+
+// Which way a start arrow points, if `symbol` is one.
+pub fn start_direction(symbol: char) -> Option<Direction> {
+    Direction::ALL
+        .iter()
+        .copied()
+        .find(|&dir| start_symbol(dir) == symbol)
+}
+
+impl fmt::Display for MapTile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MapTile::Empty => "empty",
+            MapTile::Wall => "wall",
+            MapTile::Start => "start",
+            MapTile::Food => "food",
+            MapTile::Portal => "portal",
+        };
+        f.write_str(name)
+    }
+}
+
+// A `LevelMap` is a level as it's written in a level file: the header, and a grid
+// of tiles that nobody has checked can be played yet. Reading a level file goes
+// through one of these on the way to a `Level`, and the level editor works on one
+// directly, since a level that's only half drawn usually can't be played.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelMap {
+    pub name: String,
+    pub speed: Option<f32>,
+    pub edges: EdgePolicy,
+    pub win: WinCondition,
+    // Which way the snake heads from the start.
+    pub direction: Direction,
+    width: usize,
+    height: usize,
+    // One tile per cell, row by row.
+    tiles: Vec<MapTile>,
+    // The line of the file the first row of the map is on, so that we can say
+    // where any problem with the map is.
+    first_line: usize,
+}
+
+impl LevelMap {
+    // An empty map with the default header.
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut map = LevelMap {
+            name: UNTITLED.to_string(),
+            speed: None,
+            edges: EdgePolicy::default(),
+            win: WinCondition::default(),
+            direction: Direction::Right,
+            width,
+            height,
+            tiles: vec![MapTile::Empty; width * height],
+            first_line: 1,
+        };
+        map.first_line = map.header_lines().len() + 2;
+        map
+    }
+
+    // The map of a level, as it would be written to a level file. Since portals
+    // in a level file pair up in the order they're written, a level whose portals
+    // were paired up some other way comes back with them paired in that order.
+    pub fn from_level(level: &Level) -> Self {
+        let board = &level.board;
+        let mut map = LevelMap::new(board.width.max(0) as usize, board.height.max(0) as usize);
+        map.name = level.name.clone();
+        map.speed = level.speed;
+        map.edges = board.edges;
+        map.win = level.win;
+        map.direction = level.start_dir;
+        for pos in board.obstacles() {
+            map.set(pos, MapTile::Wall);
+        }
+        for (a, b) in board.portals() {
+            map.set(a, MapTile::Portal);
+            map.set(b, MapTile::Portal);
+        }
+        for &pos in level.food.iter() {
+            map.set(pos, MapTile::Food);
+        }
+        map.set(level.start, MapTile::Start);
+        map
+    }
+
+    // Reads a map from the text of a level file. This only checks that the file
+    // is well formed; `to_level` checks that the level can be played.
+    pub fn parse(text: &str) -> Result<Self, LevelError> {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut map = LevelMap::new(0, 0);
+        // If there's a header, the map starts on the line after it ends.
+        let mut header_direction = None;
+        let map_start = match lines.iter().position(|line| line.trim() == HEADER_END) {
+            Some(end) => {
+                header_direction = map.parse_header(&lines[..end])?;
+                end + 1
+            }
+            None => 0,
+        };
+        map.direction = header_direction.unwrap_or(map.direction);
+        map.parse_rows(&lines[map_start..], map_start, header_direction)?;
+        Ok(map)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, pos: GridPosition) -> bool {
+        pos.x >= 0 && pos.y >= 0 && (pos.x as usize) < self.width && (pos.y as usize) < self.height
+    }
+
+    pub fn get(&self, pos: GridPosition) -> Option<MapTile> {
+        if self.contains(pos) {
+            Some(self.tiles[pos.y as usize * self.width + pos.x as usize])
+        } else {
+            None
+        }
+    }
+
+    // Changes the tile in a cell, and returns whether anything changed. Anything
+    // that isn't on the map is ignored.
+    pub fn set(&mut self, pos: GridPosition, tile: MapTile) -> bool {
+        if self.get(pos).is_none_or(|old| old == tile) {
+            return false;
+        }
+        self.tiles[pos.y as usize * self.width + pos.x as usize] = tile;
+        true
+    }
+
+    // Every cell on the map along with its tile, row by row.
+    pub fn tiles(&self) -> impl Iterator<Item = (GridPosition, MapTile)> + '_ {
+        let width = self.width;
+        self.tiles.iter().enumerate().map(move |(index, &tile)| {
+            let pos = GridPosition::new((index % width) as i16, (index / width) as i16);
+            (pos, tile)
+        })
+    }
+
+    // The cell a problem with this map is in, if it's about one cell in particular.
+    pub fn error_cell(&self, error: &LevelError) -> Option<GridPosition> {
+        match error.kind {
+            LevelErrorKind::UnknownTile(_)
+            | LevelErrorKind::ExtraStart
+            | LevelErrorKind::StartDirection { .. }
+            | LevelErrorKind::BlockedStart(_)
+            | LevelErrorKind::UnreachableFood
+            | LevelErrorKind::UnreachableCell
+            | LevelErrorKind::UnpairedPortal => {
+                let y = error.line.checked_sub(self.first_line)?;
+                let pos = GridPosition::new(error.column as i16 - 1, y as i16);
+                if self.contains(pos) {
+                    Some(pos)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Writes the map out as a level file. The start is written as an arrow, so the
+    // header doesn't need to say which way the snake heads.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for line in self.header_lines() {
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text.push_str(HEADER_END);
+        text.push('\n');
+        for row in self.tiles.chunks(self.width.max(1)) {
+            text.extend(row.iter().map(|&tile| match tile {
+                MapTile::Start => start_symbol(self.direction),
+                _ => tile.symbol(),
+            }));
+            text.push('\n');
+        }
+        text
+    }
+
+    fn header_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("name: {}", self.name)];
+        if let Some(speed) = self.speed {
+            lines.push(format!("speed: {}", speed));
+        }
+        lines.push(format!("edges: {}", self.edges));
+        lines.push(format!("win: {}", self.win));
+        lines
+    }
+
+    // Reads the header into the map, and returns the direction it gives the start,
+    // if it gives one. That's left for `parse_rows` to settle, since the start on
+    // the map can say which way it's heading too.
+    fn parse_header(&mut self, lines: &[&str]) -> Result<Option<Direction>, LevelError> {
+        let mut seen: Vec<&str> = Vec::new();
+        let mut direction = None;
+        for (index, line) in lines.iter().enumerate() {
+            let line_number = index + 1;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            // Columns count characters, the same as on the map, not bytes.
+            let key_column = line.chars().count() - line.trim_start().chars().count() + 1;
+            let (key, value) = line.split_once(':').ok_or_else(|| {
+                LevelError::new(line_number, key_column, LevelErrorKind::NotKeyValue)
+            })?;
+            let key = key.trim();
+            let value_column =
+                key_column + line.trim_start().chars().count() - value.trim_start().chars().count();
+            let value = value.trim();
+            if seen.contains(&key) {
+                return Err(LevelError::new(
+                    line_number,
+                    key_column,
+                    LevelErrorKind::DuplicateKey(key.to_string()),
+                ));
+            }
+            seen.push(key);
+            let invalid = |message: String| {
+                LevelError::new(
+                    line_number,
+                    value_column,
+                    LevelErrorKind::InvalidValue {
+                        key: key.to_string(),
+                        message,
+                    },
+                )
+            };
+            match key {
+                "name" if value.is_empty() => {
+                    return Err(invalid("a level's name can't be empty".to_string()))
+                }
+                "name" => self.name = value.to_string(),
+                "speed" => match value.parse::<f32>() {
+                    Ok(speed) if speed.is_finite() && speed > 0.0 => self.speed = Some(speed),
+                    _ => {
+                        return Err(invalid(format!(
+                            "expected a positive number of updates per second, not {:?}",
+                            value
+                        )))
+                    }
+                },
+                "edges" => self.edges = value.parse().map_err(invalid)?,
+                "win" => self.win = value.parse().map_err(invalid)?,
+                "direction" => direction = Some(value.parse().map_err(invalid)?),
+                _ => {
+                    return Err(LevelError::new(
+                        line_number,
+                        key_column,
+                        LevelErrorKind::UnknownKey(key.to_string()),
+                    ))
+                }
+            }
+        }
+        Ok(direction)
+    }
+
+    // Reads the rows of the map, which start `first_line` lines into the file. A
+    // start arrow sets which way the snake heads, so long as it agrees with the
+    // `header_direction`, if there was one.
+    fn parse_rows(
+        &mut self,
+        lines: &[&str],
+        first_line: usize,
+        header_direction: Option<Direction>,
+    ) -> Result<(), LevelError> {
+        // Blank lines around the map don't count as rows.
+        let skipped = lines
+            .iter()
+            .take_while(|line| line.trim().is_empty())
+            .count();
+        let end = lines
+            .iter()
+            .rposition(|line| !line.trim().is_empty())
+            .map_or(skipped, |last| last + 1);
+        let rows = &lines[skipped..end];
+        self.first_line = first_line + skipped + 1;
+        if rows.is_empty() {
+            return Err(LevelError::new(
+                self.first_line,
+                1,
+                LevelErrorKind::EmptyMap,
+            ));
+        }
+
+        self.width = rows[0].chars().count();
+        self.height = rows.len();
+        self.tiles = Vec::with_capacity(self.width * self.height);
+        for (y, row) in rows.iter().enumerate() {
+            let line = self.first_line + y;
+            let mut found = 0;
+            for (x, symbol) in row.chars().enumerate() {
+                let tile = match start_direction(symbol) {
+                    Some(dir) => {
+                        if let Some(header) = header_direction.filter(|&header| header != dir) {
+                            return Err(LevelError::new(
+                                line,
+                                x + 1,
+                                LevelErrorKind::StartDirection { header, map: dir },
+                            ));
+                        }
+                        self.direction = dir;
+                        MapTile::Start
+                    }
+                    None => MapTile::from_symbol(symbol).ok_or_else(|| {
+                        LevelError::new(line, x + 1, LevelErrorKind::UnknownTile(symbol))
+                    })?,
+                };
+                if found < self.width {
+                    self.tiles.push(tile);
+                }
+                found += 1;
+            }
+            if found != self.width {
+                return Err(LevelError::new(
+                    line,
+                    found.min(self.width) + 1,
+                    LevelErrorKind::RaggedRow {
+                        expected: self.width,
+                        found,
+                    },
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Turns the map into a level, after checking that it can be played.
+    pub fn to_level(&self) -> Result<Level, LevelError> {
+        let first_line = self.first_line;
+        let (width, height) = (self.width, self.height);
+        let sizes = MIN_BOARD_SIZE as usize..=MAX_BOARD_SIZE as usize;
+        if !sizes.contains(&width) || !sizes.contains(&height) {
+            return Err(LevelError::new(
+                first_line,
+                1,
+                LevelErrorKind::BoardSize { width, height },
+            ));
+        }
+
+        // First we go over every cell, remembering where everything is along with
+        // the line and column it came from, so we can point at it if it's a problem.
+        let mut walls = Vec::new();
+        let mut starts = Vec::new();
+        let mut food = Vec::new();
+        let mut portals = Vec::new();
+        for (pos, tile) in self.tiles() {
+            let at = (pos, first_line + pos.y as usize, pos.x as usize + 1);
+            match tile {
+                MapTile::Wall => walls.push(pos),
+                MapTile::Start => starts.push(at),
+                MapTile::Food => food.push(at),
+                MapTile::Portal => portals.push(at),
+                MapTile::Empty => (),
+            }
+        }
+
+        // There has to be exactly one start, and the portals have to pair up.
+        let (start, start_line, start_column) = match starts.as_slice() {
+            [] => return Err(LevelError::new(first_line, 1, LevelErrorKind::NoStart)),
+            [start] => *start,
+            [_, (_, line, column), ..] => {
+                return Err(LevelError::new(*line, *column, LevelErrorKind::ExtraStart))
+            }
+        };
+        if portals.len() % 2 == 1 {
+            let (_, line, column) = portals[portals.len() - 1];
+            return Err(LevelError::new(
+                line,
+                column,
+                LevelErrorKind::UnpairedPortal,
+            ));
+        }
+
+        let mut board = Board::new(width as i16, height as i16)
+            .with_edges(self.edges)
+            .with_obstacles(walls);
+        for pair in portals.chunks(2) {
+            board.add_portal(pair[0].0, pair[1].0);
+        }
+        let level = Level {
+            name: self.name.clone(),
+            board,
+            start,
+            start_dir: self.direction,
+            food: food.iter().map(|&(pos, _, _)| pos).collect(),
+            speed: self.speed,
+            win: self.win,
+        };
+
+        // Finally we make sure the level can actually be played: the snake has to fit
+        // where it starts, have somewhere to go from there, and be able to get to
+        // every piece of food and every empty cell, since that's where new food goes.
+        let blocked = |reason: String| {
+            LevelError::new(
+                start_line,
+                start_column,
+                LevelErrorKind::BlockedStart(reason),
+            )
+        };
+        level.validate().map_err(blocked)?;
+        let reachable = reachable_cells(&level.board, start);
+        let room = reachable.iter().filter(|&&reached| reached).count();
+        if room <= 2 {
+            return Err(blocked(
+                "the snake is walled in with nowhere to go".to_string(),
+            ));
+        }
+        for &(pos, line, column) in food.iter() {
+            if !reachable[index(&level.board, pos)] {
+                return Err(LevelError::new(
+                    line,
+                    column,
+                    LevelErrorKind::UnreachableFood,
+                ));
+            }
+        }
+        let sealed_off = self
+            .tiles()
+            .find(|&(pos, tile)| tile == MapTile::Empty && !reachable[index(&level.board, pos)]);
+        if let Some((pos, _)) = sealed_off {
+            return Err(LevelError::new(
+                first_line + pos.y as usize,
+                pos.x as usize + 1,
+                LevelErrorKind::UnreachableCell,
+            ));
+        }
+        Ok(level)
+    }
+}
+
+// Reads a level from the text of a level file and checks that it can be played.
+pub fn parse_level(text: &str) -> Result<Level, LevelError> {
+    LevelMap::parse(text)?.to_level()
+}
+
+// Works out which cells the snake could ever get to from `start`, going round
+// obstacles and through portals. We flood out from the start a cell at a time,
+// and return a flag for every cell, row by row.
+fn reachable_cells(board: &Board, start: GridPosition) -> Vec<bool> {
+    let mut reached = vec![false; board.cell_count()];
+    let mut queue = VecDeque::new();
+    reached[index(board, start)] = true;
+    queue.push_back(start);
+    while let Some(pos) = queue.pop_front() {
+        for &dir in Direction::ALL.iter() {
+            if let Some(next) = board.step(pos, dir) {
+                let i = index(board, next);
+                if !reached[i] && !board.is_obstacle(next) {
+                    reached[i] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    reached
+}
+
+fn index(board: &Board, pos: GridPosition) -> usize {
+    pos.y as usize * board.width as usize + pos.x as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::level::BuiltinLevel;
+
+    // Where a level file goes wrong, as the line, the column and what's wrong.
+    fn error_at(text: &str) -> (usize, usize, LevelErrorKind) {
+        let error = parse_level(text).expect_err("the level should have been refused");
+        (error.line, error.column, error.kind)
+    }
+
+    #[test]
+    fn refuses_a_room_the_snake_can_never_get_into() {
+        let text = "edges: solid\n\
+                    ---\n\
+                    .........#...\n\
+                    ..S......#...\n\
+                    .........#...\n\
+                    .........#...\n\
+                    .........#...\n";
+        assert_eq!(error_at(text), (3, 11, LevelErrorKind::UnreachableCell));
+        // Knocking a hole in the wall lets the snake in.
+        let open = text.replacen("..S......#...", "..S..........", 1);
+        assert!(parse_level(&open).is_ok());
+    }
+
+    #[test]
+    fn reads_the_header_and_the_map() {
+        let text = "name: Two Rooms\n\
+                    speed: 8\n\
+                    edges: solid\n\
+                    win: food 20\n\
+                    direction: up\n\
+                    ---\n\
+                    ##########\n\
+                    #....#..O#\n\
+                    #.S..#...#\n\
+                    #O.F.....#\n\
+                    ##########\n";
+        let level = parse_level(text).unwrap();
+        assert_eq!(level.name, "Two Rooms");
+        assert_eq!(level.speed, Some(8.0));
+        assert_eq!(level.board.edges, EdgePolicy::Solid);
+        assert_eq!(level.win, WinCondition::Food(20));
+        // An `S` only says where the snake starts, so which way it's heading comes
+        // from the header.
+        assert_eq!(level.start, GridPosition::new(2, 2));
+        assert_eq!(level.start_dir, Direction::Up);
+        assert_eq!(level.food, vec![GridPosition::new(3, 3)]);
+        assert_eq!(
+            level.board.portal_exit(GridPosition::new(8, 1)),
+            Some(GridPosition::new(1, 3))
+        );
+    }
+
+    #[test]
+    fn the_start_arrow_says_which_way_the_snake_heads() {
+        let text = "name: Arrows\n---\n.....\n.....\n..v..\n.....\n";
+        let level = parse_level(text).unwrap();
+        assert_eq!(level.start, GridPosition::new(2, 2));
+        assert_eq!(level.start_dir, Direction::Down);
+        // A header that agrees with the arrow is fine.
+        let agreed = text.replacen("---", "direction: down\n---", 1);
+        assert_eq!(parse_level(&agreed), Ok(level));
+        // Without either, the snake heads right.
+        let plain = text.replacen('v', "S", 1);
+        assert_eq!(parse_level(&plain).unwrap().start_dir, Direction::Right);
+    }
+
+    #[test]
+    fn points_at_a_start_arrow_that_disagrees_with_the_header() {
+        let text = "direction: up\n---\n.....\n..<..\n.....\n.....\n";
+        assert_eq!(
+            error_at(text),
+            (
+                4,
+                3,
+                LevelErrorKind::StartDirection {
+                    header: Direction::Up,
+                    map: Direction::Left
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn writes_the_start_as_an_arrow() {
+        let mut map = LevelMap::new(5, 4);
+        map.direction = Direction::Left;
+        map.set(GridPosition::new(1, 1), MapTile::Start);
+        let text = map.to_text();
+        assert!(!text.contains("direction"));
+        assert!(text.contains("\n.<...\n"));
+        let read = LevelMap::parse(&text).unwrap();
+        assert_eq!(read.direction, Direction::Left);
+        assert_eq!(read.get(GridPosition::new(1, 1)), Some(MapTile::Start));
+    }
+
+    #[test]
+    fn points_at_an_unknown_header_key() {
+        let text = "name: Mine\n  colour: red\n---\n.....\n..S..\n.....\n.....\n";
+        assert_eq!(
+            error_at(text),
+            (2, 3, LevelErrorKind::UnknownKey("colour".to_string()))
+        );
+    }
+
+    #[test]
+    fn counts_header_columns_in_characters() {
+        // The non-breaking space is two bytes, but only one column, just like a
+        // character on the map.
+        let text = "\u{a0}speed:  fast\n---\n.....\n..S..\n.....\n.....\n";
+        let (line, column, _) = error_at(text);
+        assert_eq!((line, column), (1, 10));
+        let text = "\u{a0}wïn: lots\n---\n.....\n..S..\n.....\n.....\n";
+        let (line, column, _) = error_at(text);
+        assert_eq!((line, column), (1, 2));
+    }
+
+    #[test]
+    fn points_at_the_end_of_a_ragged_row() {
+        let short = ".....\n..S..\n...\n.....\n";
+        assert_eq!(
+            error_at(short),
+            (
+                3,
+                4,
+                LevelErrorKind::RaggedRow {
+                    expected: 5,
+                    found: 3
+                }
+            )
+        );
+        let long = "---\n.....\n..S..\n.......\n.....\n";
+        assert_eq!(
+            error_at(long),
+            (
+                4,
+                6,
+                LevelErrorKind::RaggedRow {
+                    expected: 5,
+                    found: 7
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn points_at_an_unknown_tile() {
+        let text = ".....\n..S.x\n.....\n.....\n";
+        assert_eq!(error_at(text), (2, 5, LevelErrorKind::UnknownTile('x')));
+    }
+
+    #[test]
+    fn points_at_the_map_when_there_is_no_start() {
+        let text = "name: Nowhere\n---\n\n.....\n.....\n.....\n.....\n";
+        assert_eq!(error_at(text), (4, 1, LevelErrorKind::NoStart));
+    }
+
+    #[test]
+    fn points_at_the_second_start() {
+        let text = ".....\n..S..\n.....\n...S.\n";
+        assert_eq!(error_at(text), (4, 4, LevelErrorKind::ExtraStart));
+    }
+
+    #[test]
+    fn points_at_food_the_snake_can_never_reach() {
+        let text = "edges: solid\n\
+                    ---\n\
+                    .........#...\n\
+                    ..S......#...\n\
+                    .........#.F.\n\
+                    .........#...\n\
+                    .........#...\n";
+        assert_eq!(error_at(text), (5, 12, LevelErrorKind::UnreachableFood));
+    }
+
+    #[test]
+    fn every_builtin_level_can_be_reached_all_over() {
+        let config = GameConfig::default();
+        for &preset in BuiltinLevel::ALL.iter() {
+            let level = config.level(preset);
+            assert_eq!(LevelMap::from_level(&level).to_level(), Ok(level));
+        }
+    }
+}
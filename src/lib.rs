@@ -0,0 +1,49 @@
+// This library holds the actual game of snake: the board, the snake, the food
+// and the rules that tie them together. None of it depends on `ggez`, so it can
+// be driven by a window, a test, a bot, or anything else that wants to play.
+// The `ggez` frontend lives in `main.rs` and only draws the game and feeds it input.
+
+mod board;
+mod config;
+mod controls;
+mod difficulty;
+mod editor;
+mod food;
+mod gamepad;
+mod grid;
+mod highscore;
+mod level;
+mod levelfile;
+mod occupancy;
+mod render;
+mod score;
+mod simulation;
+mod snake;
+mod theme;
+mod timestep;
+mod versus;
+
+pub use board::{Board, BoardView, EdgePolicy};
+pub use config::{ConfigError, GameConfig, MAX_BOARD_SIZE, MIN_BOARD_SIZE};
+pub use controls::{Action, Conflict, ControlMode, KeyBindings, PlayerBindings};
+pub use difficulty::{Difficulty, DifficultyPreset};
+pub use editor::{LevelEditor, MAX_UNDO};
+pub use food::{Food, FoodField, FoodKind};
+pub use gamepad::{stick_direction, AnalogStick, Controllers, DEFAULT_DEADZONE};
+pub use grid::{Direction, GridPosition, Rotation};
+pub use highscore::{format_date, HighScore, HighScoreTable, HIGH_SCORE_COUNT};
+pub use level::{BuiltinLevel, Level, WinCondition};
+pub use levelfile::{
+    parse_level, LevelError, LevelErrorKind, LevelMap, MapTile, HEADER_END, UNTITLED,
+};
+pub use occupancy::OccupancyGrid;
+pub use render::{
+    board_quads, board_sprites, editor_quads, food_quads, grid_quads, obstacle_quads, portal_quads,
+    snake_quads, snake_sprites, terrain_quads, wall_quads, Quad, Rect, Sprite, Tile,
+};
+pub use score::{Score, ScoreRules};
+pub use simulation::{Event, Events, Outcome, Simulation};
+pub use snake::{Ate, Segment, Snake, Steer, MAX_QUEUED_TURNS};
+pub use theme::{mix, BuiltinTheme, Color, FoodColors, SnakeColors, Theme};
+pub use timestep::{FixedTimestep, MAX_CATCH_UP_TICKS};
+pub use versus::{VersusMatch, MAX_PLAYERS};
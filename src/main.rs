@@ -1,381 +1,1427 @@
 // First we'll import the crates we need for our game;
-// in this case that is just `ggez` and `rand`.
+// in this case that is `ggez` and our own `ggez_snake` library,
+// which holds the actual game simulation.
 // Next we need to actually `use` the pieces of ggez that we are going
 // to need frequently.
-use ggez::event::KeyCode;
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods, MouseButton};
 use ggez::{event, graphics, Context, GameResult};
 
 // We'll bring in some things from `std` to help us in the future.
-use std::collections::LinkedList;
-use std::time::{Duration, Instant};
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-// And finally bring the `Rng` trait into scope so that we can generate
-// some random numbers later.
-use rand::Rng;
+// And finally we bring in the parts of the simulation that we need to draw
+// and drive the game.
+use ggez_snake::{
+    board_quads, board_sprites, editor_quads, format_date, parse_level, terrain_quads, Action,
+    BuiltinLevel, BuiltinTheme, ControlMode, Controllers, DifficultyPreset, Direction, EdgePolicy,
+    FixedTimestep, GameConfig, GridPosition, HighScore, HighScoreTable, KeyBindings, Level,
+    LevelEditor, LevelMap, MapTile, Outcome, Quad, Simulation, Sprite, Steer, Theme, Tile,
+    VersusMatch, MAX_PLAYERS,
+};
 
 // The first thing we want to do is set up some constants that will help us out later.
 
-// Here we define the size of our game board in terms of how many grid
-// cells it will take up. We choose to make a 30 x 20 game board.
-const GRID_SIZE: (i16, i16) = (30, 20);
-// Now we define the pixel size of each tile, which we make 32x32 pixels.
-const GRID_CELL_SIZE: (i16, i16) = (32, 32);
-
-// Next we define how large we want our actual window to be by multiplying
-// the components of our grid size by its corresponding pixel size.
-const SCREEN_SIZE: (u32, u32) = (
-    GRID_SIZE.0 as u32 * GRID_CELL_SIZE.0 as u32,
-    GRID_SIZE.1 as u32 * GRID_CELL_SIZE.1 as u32,
-);
-
-// Here we're defining how many quickly we want our game to update. This will be
-// important later so that we don't have our snake fly across the screen because
-// it's moving a full tile every frame.
-const UPDATES_PER_SECOND: f32 = 8.0;
-// And we get the milliseconds of delay that this update rate corresponds to.
-const MILLIS_PER_UPDATE: u64 = (1.0 / UPDATES_PER_SECOND * 1000.0) as u64;
-
-// Now we define a struct that will hold an entity's position on our game board
-// or grid which we defined above. We'll use signed integers because we only want
-// to store whole numbers, and we need them to be signed so that they work properly
-// with our modulus arithmetic later.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-struct GridPosition {
-    x: i16,
-    y: i16,
+// If we aren't pointed at a config file on the command line, we look for one
+// with this name in the directory the game was started from.
+const DEFAULT_CONFIG_PATH: &str = "snake.toml";
+
+// This is where the high score table lives, relative to ggez's user data directory.
+const HIGH_SCORES_PATH: &str = "/highscores.toml";
+// If the high score file can't be read, we move it here so that the next save
+// doesn't overwrite it before someone has had a chance to look at it.
+const CORRUPT_HIGH_SCORES_PATH: &str = "/highscores.corrupt.toml";
+// Where the key bindings are kept, also in the user data directory, and where
+// they're moved if they can't be read, just like the high scores.
+const CONTROLS_PATH: &str = "/controls.toml";
+const CORRUPT_CONTROLS_PATH: &str = "/controls.corrupt.toml";
+// How many times faster the game runs while the speed boost key is held down.
+const SPEED_BOOST: u32 = 2;
+// The longest name a player can enter for the high score table.
+const MAX_NAME_LENGTH: usize = 12;
+// Where the level editor saves levels to and loads them from. Like the high scores,
+// saving puts it in ggez's user data directory, so point `level_file` at the same
+// path to play it from the title screen.
+const EDITOR_LEVEL_PATH: &str = "/levels/custom.txt";
+// The tiles the editor can paint with, picked with the number keys in this order.
+const BRUSHES: [MapTile; 5] = [
+    MapTile::Wall,
+    MapTile::Start,
+    MapTile::Food,
+    MapTile::Portal,
+    MapTile::Empty,
+];
+
+// These are the options that can be passed on the command line when starting the game.
+struct Options {
+    // The seed for the game's random number generator. Passing the seed shown on
+    // the game over screen replays that exact game.
+    seed: Option<u64>,
+    // The board size, cell size, speed and window size to play with. These come
+    // from the config file, with any settings given on the command line on top.
+    config: GameConfig,
 }
 
-// This is a trait that provides a modulus function that works for negative values
-// rather than just the standard remainder op (%) which does not. We'll use this
-// to get our snake to wrap from one side of the game board around to the other
-// when it goes off the top, bottom, left, or right side of the screen.
-trait ModuloSigned {
-    fn modulo(&self, n: Self) -> Self;
+impl Options {
+    // We read the options from the process arguments, skipping the first one
+    // which is just the name of our program. Every option takes a value.
+    fn from_args() -> GameResult<Self> {
+        let mut seed = None;
+        let mut config_path = None;
+        let mut overrides = Vec::new();
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| config_error(format!("{} needs a value", flag)))?;
+            match flag.as_str() {
+                "--seed" => seed = Some(parse_value(&flag, &value)?),
+                "--config" => config_path = Some(value),
+                "--width" | "--height" | "--edges" | "--cell-size" | "--food" | "--difficulty"
+                | "--level" | "--speed" | "--window-width" | "--window-height" | "--level-file"
+                | "--tileset" | "--theme" | "--theme-file" | "--players" | "--rounds"
+                | "--deadzone" => overrides.push((flag, value)),
+                _ => return Err(config_error(format!("unknown argument: {}", flag))),
+            }
+        }
+
+        // Once we know where the config file is, we load it and then apply
+        // whatever was set on the command line over the top.
+        let mut config = load_config(config_path.as_deref())?;
+        for (flag, value) in overrides {
+            match flag.as_str() {
+                "--width" => config.board_width = parse_value(&flag, &value)?,
+                "--height" => config.board_height = parse_value(&flag, &value)?,
+                "--edges" => config.edges = parse_value(&flag, &value)?,
+                "--cell-size" => config.cell_size = parse_value(&flag, &value)?,
+                "--food" => config.food_count = parse_value(&flag, &value)?,
+                "--difficulty" => config.difficulty = parse_value(&flag, &value)?,
+                "--level" => config.level = parse_value(&flag, &value)?,
+                "--level-file" => config.level_file = Some(value),
+                "--speed" => config.updates_per_second = Some(parse_value(&flag, &value)?),
+                "--window-width" => config.window_width = Some(parse_value(&flag, &value)?),
+                "--window-height" => config.window_height = Some(parse_value(&flag, &value)?),
+                "--tileset" => config.tileset = Some(value),
+                "--theme" => config.theme = parse_value(&flag, &value)?,
+                "--theme-file" => config.theme_file = Some(value),
+                "--players" => config.players = parse_value(&flag, &value)?,
+                "--rounds" => config.rounds_to_win = parse_value(&flag, &value)?,
+                "--deadzone" => config.gamepad_deadzone = parse_value(&flag, &value)?,
+                _ => unreachable!("only known flags are collected as overrides"),
+            }
+        }
+        config.validate().map_err(config_error)?;
+        Ok(Options { seed, config })
+    }
+}
+
+// Reads the config file at `path`, or at `DEFAULT_CONFIG_PATH` if we weren't
+// given one. It's fine for the default file not to exist, in which case we
+// use the default config, but a file we were explicitly pointed at must be there.
+fn load_config(path: Option<&str>) -> GameResult<GameConfig> {
+    let text = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| config_error(format!("couldn't read {}: {}", path, e)))?,
+        None => match std::fs::read_to_string(DEFAULT_CONFIG_PATH) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(GameConfig::default()),
+            Err(e) => {
+                return Err(config_error(format!(
+                    "couldn't read {}: {}",
+                    DEFAULT_CONFIG_PATH, e
+                )))
+            }
+        },
+    };
+    GameConfig::from_toml(&text).map_err(config_error)
 }
 
-// Here we implement our `ModuloSigned` trait for any type T which implements
-// `Add` (the `+` operator) with an output type T and Rem (the `%` operator)
-// that also has anout put type of T, and that can be cloned. These are the bounds
-// that we need in order to implement a modulus function that works for negative numbers
-// as well.
-impl<T> ModuloSigned for T
-where
-    T: std::ops::Add<Output = T> + std::ops::Rem<Output = T> + Clone,
-{
-    fn modulo(&self, n: T) -> T {
-        // Because of our trait bounds, we can now apply these operators.
-        (self.clone() % n.clone() + n.clone()) % n
+// Gathers up the themes the player can switch between: every built-in theme, plus
+// the one from the config's theme file if it has one. We also return which of them
+// to start with. Like the tileset and level files, the theme file is found in the
+// `resources` directory or the game's user data directory.
+fn load_themes(ctx: &mut Context, config: &GameConfig) -> GameResult<(Vec<Theme>, usize)> {
+    let mut themes: Vec<Theme> = BuiltinTheme::ALL.iter().map(|t| t.theme()).collect();
+    let start = match &config.theme_file {
+        Some(path) => {
+            let mut text = String::new();
+            ggez::filesystem::open(ctx, path)
+                .and_then(|mut file| Ok(file.read_to_string(&mut text)?))
+                .map_err(|e| config_error(format!("couldn't read {}: {}", path, e)))?;
+            let theme = Theme::from_toml(&text)
+                .map_err(|e| config_error(format!("couldn't parse theme {}: {}", path, e)))?;
+            themes.push(theme);
+            themes.len() - 1
+        }
+        None => BuiltinTheme::ALL
+            .iter()
+            .position(|&t| t == config.theme)
+            .unwrap_or(0),
+    };
+    Ok((themes, start))
+}
+
+// A couple of small helpers for reporting bad options.
+fn config_error<E: std::fmt::Display>(e: E) -> ggez::GameError {
+    ggez::GameError::ConfigError(e.to_string())
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> GameResult<T> {
+    value
+        .parse()
+        .map_err(|_| config_error(format!("invalid value for {}: {}", flag, value)))
+}
+
+// We also create a helper function that will let us convert between a
+// `ggez` `KeyCode` and the name it goes by in the key bindings, like `Up`, `W` or
+// `Space`. The bindings then tell us which action, if any, the key stands for.
+// These names end up in players' `controls.toml` files, so they're written out
+// here rather than borrowed from however `KeyCode` happens to print itself, which
+// could change under us with a new version of ggez. They're the names the keys
+// have always gone by, so older files still work.
+fn key_name(key: KeyCode) -> &'static str {
+    match key {
+        KeyCode::Key1 => "Key1",
+        KeyCode::Key2 => "Key2",
+        KeyCode::Key3 => "Key3",
+        KeyCode::Key4 => "Key4",
+        KeyCode::Key5 => "Key5",
+        KeyCode::Key6 => "Key6",
+        KeyCode::Key7 => "Key7",
+        KeyCode::Key8 => "Key8",
+        KeyCode::Key9 => "Key9",
+        KeyCode::Key0 => "Key0",
+        KeyCode::A => "A",
+        KeyCode::B => "B",
+        KeyCode::C => "C",
+        KeyCode::D => "D",
+        KeyCode::E => "E",
+        KeyCode::F => "F",
+        KeyCode::G => "G",
+        KeyCode::H => "H",
+        KeyCode::I => "I",
+        KeyCode::J => "J",
+        KeyCode::K => "K",
+        KeyCode::L => "L",
+        KeyCode::M => "M",
+        KeyCode::N => "N",
+        KeyCode::O => "O",
+        KeyCode::P => "P",
+        KeyCode::Q => "Q",
+        KeyCode::R => "R",
+        KeyCode::S => "S",
+        KeyCode::T => "T",
+        KeyCode::U => "U",
+        KeyCode::V => "V",
+        KeyCode::W => "W",
+        KeyCode::X => "X",
+        KeyCode::Y => "Y",
+        KeyCode::Z => "Z",
+        KeyCode::Escape => "Escape",
+        KeyCode::F1 => "F1",
+        KeyCode::F2 => "F2",
+        KeyCode::F3 => "F3",
+        KeyCode::F4 => "F4",
+        KeyCode::F5 => "F5",
+        KeyCode::F6 => "F6",
+        KeyCode::F7 => "F7",
+        KeyCode::F8 => "F8",
+        KeyCode::F9 => "F9",
+        KeyCode::F10 => "F10",
+        KeyCode::F11 => "F11",
+        KeyCode::F12 => "F12",
+        KeyCode::F13 => "F13",
+        KeyCode::F14 => "F14",
+        KeyCode::F15 => "F15",
+        KeyCode::F16 => "F16",
+        KeyCode::F17 => "F17",
+        KeyCode::F18 => "F18",
+        KeyCode::F19 => "F19",
+        KeyCode::F20 => "F20",
+        KeyCode::F21 => "F21",
+        KeyCode::F22 => "F22",
+        KeyCode::F23 => "F23",
+        KeyCode::F24 => "F24",
+        KeyCode::Snapshot => "Snapshot",
+        KeyCode::Scroll => "Scroll",
+        KeyCode::Pause => "Pause",
+        KeyCode::Insert => "Insert",
+        KeyCode::Home => "Home",
+        KeyCode::Delete => "Delete",
+        KeyCode::End => "End",
+        KeyCode::PageDown => "PageDown",
+        KeyCode::PageUp => "PageUp",
+        KeyCode::Left => "Left",
+        KeyCode::Up => "Up",
+        KeyCode::Right => "Right",
+        KeyCode::Down => "Down",
+        KeyCode::Back => "Back",
+        KeyCode::Return => "Return",
+        KeyCode::Space => "Space",
+        KeyCode::Compose => "Compose",
+        KeyCode::Caret => "Caret",
+        KeyCode::Numlock => "Numlock",
+        KeyCode::Numpad0 => "Numpad0",
+        KeyCode::Numpad1 => "Numpad1",
+        KeyCode::Numpad2 => "Numpad2",
+        KeyCode::Numpad3 => "Numpad3",
+        KeyCode::Numpad4 => "Numpad4",
+        KeyCode::Numpad5 => "Numpad5",
+        KeyCode::Numpad6 => "Numpad6",
+        KeyCode::Numpad7 => "Numpad7",
+        KeyCode::Numpad8 => "Numpad8",
+        KeyCode::Numpad9 => "Numpad9",
+        KeyCode::NumpadAdd => "NumpadAdd",
+        KeyCode::NumpadDivide => "NumpadDivide",
+        KeyCode::NumpadDecimal => "NumpadDecimal",
+        KeyCode::NumpadComma => "NumpadComma",
+        KeyCode::NumpadEnter => "NumpadEnter",
+        KeyCode::NumpadEquals => "NumpadEquals",
+        KeyCode::NumpadMultiply => "NumpadMultiply",
+        KeyCode::NumpadSubtract => "NumpadSubtract",
+        KeyCode::AbntC1 => "AbntC1",
+        KeyCode::AbntC2 => "AbntC2",
+        KeyCode::Apostrophe => "Apostrophe",
+        KeyCode::Apps => "Apps",
+        KeyCode::Asterisk => "Asterisk",
+        KeyCode::At => "At",
+        KeyCode::Ax => "Ax",
+        KeyCode::Backslash => "Backslash",
+        KeyCode::Calculator => "Calculator",
+        KeyCode::Capital => "Capital",
+        KeyCode::Colon => "Colon",
+        KeyCode::Comma => "Comma",
+        KeyCode::Convert => "Convert",
+        KeyCode::Equals => "Equals",
+        KeyCode::Grave => "Grave",
+        KeyCode::Kana => "Kana",
+        KeyCode::Kanji => "Kanji",
+        KeyCode::LAlt => "LAlt",
+        KeyCode::LBracket => "LBracket",
+        KeyCode::LControl => "LControl",
+        KeyCode::LShift => "LShift",
+        KeyCode::LWin => "LWin",
+        KeyCode::Mail => "Mail",
+        KeyCode::MediaSelect => "MediaSelect",
+        KeyCode::MediaStop => "MediaStop",
+        KeyCode::Minus => "Minus",
+        KeyCode::Mute => "Mute",
+        KeyCode::MyComputer => "MyComputer",
+        KeyCode::NavigateForward => "NavigateForward",
+        KeyCode::NavigateBackward => "NavigateBackward",
+        KeyCode::NextTrack => "NextTrack",
+        KeyCode::NoConvert => "NoConvert",
+        KeyCode::OEM102 => "OEM102",
+        KeyCode::Period => "Period",
+        KeyCode::PlayPause => "PlayPause",
+        KeyCode::Plus => "Plus",
+        KeyCode::Power => "Power",
+        KeyCode::PrevTrack => "PrevTrack",
+        KeyCode::RAlt => "RAlt",
+        KeyCode::RBracket => "RBracket",
+        KeyCode::RControl => "RControl",
+        KeyCode::RShift => "RShift",
+        KeyCode::RWin => "RWin",
+        KeyCode::Semicolon => "Semicolon",
+        KeyCode::Slash => "Slash",
+        KeyCode::Sleep => "Sleep",
+        KeyCode::Stop => "Stop",
+        KeyCode::Sysrq => "Sysrq",
+        KeyCode::Tab => "Tab",
+        KeyCode::Underline => "Underline",
+        KeyCode::Unlabeled => "Unlabeled",
+        KeyCode::VolumeDown => "VolumeDown",
+        KeyCode::VolumeUp => "VolumeUp",
+        KeyCode::Wake => "Wake",
+        KeyCode::WebBack => "WebBack",
+        KeyCode::WebFavorites => "WebFavorites",
+        KeyCode::WebForward => "WebForward",
+        KeyCode::WebHome => "WebHome",
+        KeyCode::WebRefresh => "WebRefresh",
+        KeyCode::WebSearch => "WebSearch",
+        KeyCode::WebStop => "WebStop",
+        KeyCode::Yen => "Yen",
+        KeyCode::Copy => "Copy",
+        KeyCode::Paste => "Paste",
+        KeyCode::Cut => "Cut",
     }
 }
 
-impl GridPosition {
-    // We make a standard helper function so that we can create a new `GridPosition`
-    // more easily.
-    pub fn new(x: i16, y: i16) -> Self {
-        GridPosition { x, y }
+// Controllers aren't rebindable: the D-pad steers, start pauses, select restarts
+// and holding the bottom face button (A on most controllers) is the speed boost.
+fn gamepad_action(button: Button) -> Option<Action> {
+    match button {
+        Button::DPadUp => Some(Action::Turn(Direction::Up)),
+        Button::DPadDown => Some(Action::Turn(Direction::Down)),
+        Button::DPadLeft => Some(Action::Turn(Direction::Left)),
+        Button::DPadRight => Some(Action::Turn(Direction::Right)),
+        Button::Start => Some(Action::Pause),
+        Button::Select => Some(Action::Restart),
+        Button::South => Some(Action::SpeedBoost),
+        _ => None,
     }
+}
 
-    // As well as a helper function that will give us a random `GridPosition` from
-    // `(0, 0)` to `(max_x, max_y)`
-    pub fn random(max_x: i16, max_y: i16) -> Self {
-        let mut rng = rand::thread_rng();
-        // We can use `.into()` to convert from `(i16, i16)` to a `GridPosition` since
-        // we implement `From<(i16, i16)>` for `GridPosition` below.
-        (rng.gen_range(0..max_x), rng.gen_range(0..max_y)).into()
+// Here is the first time we see what drawing looks like with ggez.
+// We have a function that takes in a `&mut ggez::Context` which we use
+// with the helpers in `ggez::graphics` to do drawing. We also return a
+// `ggez::GameResult` so that we can use the `?` operator to bubble up
+// failure of drawing.
+//
+// The library has already worked out every rectangle that makes up the board,
+// so all we have to do is put them on the screen. Building a separate mesh for
+// every rectangle would mean a separate draw call for every segment of the
+// snake, so instead we add them all to a single `MeshBuilder` and draw the
+// whole board at once.
+fn draw_quads(ctx: &mut Context, quads: &[Quad]) -> GameResult {
+    // A mesh with nothing in it can't be built, so if there's nothing to draw
+    // we're already done.
+    if quads.is_empty() {
+        return Ok(());
     }
+    let mut builder = graphics::MeshBuilder::new();
+    for quad in quads {
+        let rect = quad.rect;
+        builder.rectangle(
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(rect.x, rect.y, rect.w, rect.h),
+            quad.color.into(),
+        )?;
+    }
+    let mesh = builder.build(ctx)?;
+    graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
+    Ok(())
+}
 
-    // We'll make another helper function that takes one grid position and returns a new one after
-    // making one move in the direction of `dir`. We use our `SignedModulo` trait
-    // above, which is now implemented on `i16` because it satisfies the trait bounds,
-    // to automatically wrap around within our grid size if the move would have otherwise
-    // moved us off the board to the top, bottom, left, or right.
-    pub fn new_from_move(pos: GridPosition, dir: Direction) -> Self {
-        match dir {
-            Direction::Up => GridPosition::new(pos.x, (pos.y - 1).modulo(GRID_SIZE.1)),
-            Direction::Down => GridPosition::new(pos.x, (pos.y + 1).modulo(GRID_SIZE.1)),
-            Direction::Left => GridPosition::new((pos.x - 1).modulo(GRID_SIZE.0), pos.y),
-            Direction::Right => GridPosition::new((pos.x + 1).modulo(GRID_SIZE.0), pos.y),
-        }
+// When we have a tileset, we draw the snake and food from it instead. Every sprite
+// cuts its tile out of the tileset and is scaled and turned to fit its rectangle.
+// They all go into one `SpriteBatch`, so it's still a single draw call.
+fn draw_sprites(ctx: &mut Context, tileset: &graphics::Image, sprites: &[Sprite]) -> GameResult {
+    let tile_count = Tile::ALL.len() as f32;
+    let tile_width = tileset.width() as f32 / tile_count;
+    let tile_height = tileset.height() as f32;
+    let mut batch = graphics::spritebatch::SpriteBatch::new(tileset.clone());
+    for sprite in sprites {
+        let rect = sprite.rect;
+        batch.add(
+            graphics::DrawParam::new()
+                // The source rectangle is measured in fractions of the whole image.
+                .src(graphics::Rect::new(
+                    sprite.tile.index() as f32 / tile_count,
+                    0.0,
+                    1.0 / tile_count,
+                    1.0,
+                ))
+                // We turn the tile around its middle, so we place its middle in the
+                // middle of the rectangle.
+                .offset([0.5, 0.5])
+                .dest([rect.x + rect.w / 2.0, rect.y + rect.h / 2.0])
+                .rotation(sprite.rotation())
+                .scale([rect.w / tile_width, rect.h / tile_height])
+                .color(sprite.tint.into()),
+        );
     }
+    graphics::draw(ctx, &batch, graphics::DrawParam::default())?;
+    Ok(())
 }
 
-// We implement the `From` trait, which in this case allows us to convert easily between
-// a GridPosition and a ggez `graphics::Rect` which fills that grid cell.
-// Now we can just call `.into()` on a `GridPosition` where we want a
-// `Rect` that represents that grid cell.
-impl From<GridPosition> for graphics::Rect {
-    fn from(pos: GridPosition) -> Self {
-        graphics::Rect::new_i32(
-            pos.x as i32 * GRID_CELL_SIZE.0 as i32,
-            pos.y as i32 * GRID_CELL_SIZE.1 as i32,
-            GRID_CELL_SIZE.0 as i32,
-            GRID_CELL_SIZE.1 as i32,
-        )
+// Gathers up the levels the player can pick from: every built-in level laid out on
+// the board from the config, plus the one from the config's level file if it has
+// one. We also return which of them to start with. Unlike a missing tileset, a level
+// file that's missing or broken stops the game from starting, with the line and
+// column of whatever is wrong with it.
+fn load_levels(ctx: &mut Context, config: &GameConfig) -> GameResult<(Vec<Level>, usize)> {
+    let mut levels: Vec<Level> = BuiltinLevel::ALL.iter().map(|&l| config.level(l)).collect();
+    let start = match &config.level_file {
+        Some(path) => {
+            let mut text = String::new();
+            ggez::filesystem::open(ctx, path)
+                .and_then(|mut file| Ok(file.read_to_string(&mut text)?))
+                .map_err(|e| config_error(format!("couldn't read level {}: {}", path, e)))?;
+            let level = parse_level(&text)
+                .map_err(|e| config_error(format!("couldn't load level {}: {}", path, e)))?;
+            levels.push(level);
+            levels.len() - 1
+        }
+        None => BuiltinLevel::ALL
+            .iter()
+            .position(|&l| l == config.level)
+            .unwrap_or(0),
+    };
+    Ok((levels, start))
+}
+
+// We always draw in board pixels, so if the window is a different size than the
+// board, ggez scales everything we draw to fit. Levels can have boards of their
+// own size, so we do this whenever a game starts or the editor opens a level.
+fn fit_screen_to_board(ctx: &mut Context, width: usize, height: usize, cell_size: u16) {
+    let width = width as f32 * cell_size as f32;
+    let height = height as f32 * cell_size as f32;
+    if let Err(e) =
+        graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height))
+    {
+        eprintln!("Couldn't fit the board to the window: {}", e);
     }
 }
 
-// And here we implement `From` again to allow us to easily convert between
-// `(i16, i16)` and a `GridPosition`.
-impl From<(i16, i16)> for GridPosition {
-    fn from(pos: (i16, i16)) -> Self {
-        GridPosition { x: pos.0, y: pos.1 }
+// Works out which cell of the board the mouse is over. ggez tells us where the
+// mouse is in window pixels, so we scale that to the board pixels we draw in first.
+fn mouse_cell(ctx: &Context, x: f32, y: f32, cell_size: u16) -> Option<GridPosition> {
+    let screen = graphics::screen_coordinates(ctx);
+    let (window_width, window_height) = graphics::drawable_size(ctx);
+    let x = screen.x + x / window_width * screen.w;
+    let y = screen.y + y / window_height * screen.h;
+    if x < 0.0 || y < 0.0 {
+        return None;
     }
+    let cell_size = cell_size as f32;
+    Some(GridPosition::new(
+        (x / cell_size) as i16,
+        (y / cell_size) as i16,
+    ))
 }
 
-// Next we create an enum that will represent all the possible
-// directions that our snake could move.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+// The edge policy after this one, for switching between them in the editor.
+fn next_edges(edges: EdgePolicy) -> EdgePolicy {
+    match edges {
+        EdgePolicy::Wrap => EdgePolicy::Solid,
+        EdgePolicy::Solid => EdgePolicy::Bounce,
+        EdgePolicy::Bounce => EdgePolicy::Wrap,
+    }
 }
 
-impl Direction {
-    // We create a helper function that will allow us to easily get the inverse
-    // of a `Direction` which we can use later to check if the player should be
-    // able to move the snake in a certain direction.
-    pub fn inverse(&self) -> Self {
-        match *self {
-            Direction::Up => Direction::Down,
-            Direction::Down => Direction::Up,
-            Direction::Left => Direction::Right,
-            Direction::Right => Direction::Left,
-        }
-    }
-
-    // We also create a helper function that will let us convert between a
-    // `ggez` `KeyCode` and the `Direction` that it represents. Of course,
-    // not every keycode represents a direction, so we return `None` if this
-    // is the case.
-    pub fn from_keycode(key: KeyCode) -> Option<Direction> {
-        match key {
-            KeyCode::Up => Some(Direction::Up),
-            KeyCode::Down => Some(Direction::Down),
-            KeyCode::Left => Some(Direction::Left),
-            KeyCode::Right => Some(Direction::Right),
-            _ => None,
+// Loads the tileset named in the config, if there is one. If it can't be loaded we
+// say why and carry on drawing plain rectangles instead.
+fn load_tileset(ctx: &mut Context, config: &GameConfig) -> Option<graphics::Image> {
+    let path = config.tileset.as_ref()?;
+    match graphics::Image::new(ctx, path) {
+        Ok(mut image) => {
+            // Tiles are usually small pixel art, which should stay crisp when scaled up.
+            image.set_filter(graphics::FilterMode::Nearest);
+            Some(image)
+        }
+        Err(e) => {
+            eprintln!(
+                "Couldn't load tileset {} ({}); drawing rectangles instead",
+                path, e
+            );
+            None
         }
     }
 }
 
-// This is mostly just a semantic abstraction over a `GridPosition` to represent
-// a segment of the snake. It could be useful to, say, have each segment contain its
-// own color or something similar. This is an exercise left up to the reader ;)
-#[derive(Clone, Copy, Debug)]
-struct Segment {
-    pos: GridPosition,
+// This helper writes a message in the middle of the screen, which we use for
+// the title screen, the pause screen and the game over screen.
+fn draw_centered_text(ctx: &mut Context, message: &str, color: graphics::Color) -> GameResult {
+    let text = graphics::Text::new(message);
+    // We measure the text and the screen so that we can center one on the other.
+    let dims = text.dimensions(ctx);
+    let screen = graphics::screen_coordinates(ctx);
+    let dest = [(screen.w - dims.w) / 2.0, (screen.h - dims.h) / 2.0];
+    graphics::draw(
+        ctx,
+        &text,
+        graphics::DrawParam::default().dest(dest).color(color),
+    )?;
+    Ok(())
 }
 
-impl Segment {
-    pub fn new(pos: GridPosition) -> Self {
-        Segment { pos }
+// The HUD sits in the top left corner and tells the player how they're doing. In a
+// match between several players, it has a line for each of them and the state of
+// the match in place of the best score.
+fn draw_hud(
+    ctx: &mut Context,
+    sim: &Simulation,
+    versus: Option<&VersusMatch>,
+    elapsed: Duration,
+    best_score: u32,
+    color: graphics::Color,
+) -> GameResult {
+    let seconds = elapsed.as_secs();
+    let status = format!(
+        "Level: {} ({:.1}/s)   Time: {:02}:{:02}",
+        sim.level(),
+        sim.speed(),
+        seconds / 60,
+        seconds % 60,
+    );
+    let text = match versus {
+        None => {
+            let score = sim.score();
+            format!(
+                "Score: {} (x{})   Length: {}   {}   Best: {}",
+                score.points(),
+                score.multiplier(),
+                sim.snake().length(),
+                status,
+                best_score,
+            )
+        }
+        Some(versus) => {
+            let mut text = format!(
+                "Round {}, first to {} wins   {}",
+                versus.rounds_played() + 1,
+                versus.rounds_to_win(),
+                status
+            );
+            for (player, score) in sim.scores().iter().enumerate() {
+                let length = match sim.is_alive(player) {
+                    true => sim.snakes()[player].length().to_string(),
+                    false => "out".to_string(),
+                };
+                text.push_str(&format!(
+                    "\nPlayer {}: {} (x{})   Length: {}",
+                    player + 1,
+                    score.points(),
+                    score.multiplier(),
+                    length
+                ));
+            }
+            text
+        }
+    };
+    let text = graphics::Text::new(text);
+    graphics::draw(
+        ctx,
+        &text,
+        graphics::DrawParam::default().dest([8.0, 8.0]).color(color),
+    )?;
+    Ok(())
+}
+
+// The key bindings are loaded from disk too. If there aren't any, we play with the
+// defaults. If they can't be used, we play with the defaults as well, but first we
+// set the file aside like a broken high score table, so that saving from the
+// controls menu doesn't overwrite whatever was in it. The error says where it went,
+// and the controls menu shows it.
+fn load_controls(ctx: &mut Context) -> Result<KeyBindings, String> {
+    if !ggez::filesystem::exists(ctx, CONTROLS_PATH) {
+        return Ok(KeyBindings::default());
     }
+    // As with the high scores, we read the raw bytes, so that a file that isn't
+    // valid UTF-8 is still set aside whole rather than as an empty file.
+    let mut bytes = Vec::new();
+    let read = ggez::filesystem::open(ctx, CONTROLS_PATH)
+        .and_then(|mut file| Ok(file.read_to_end(&mut bytes)?));
+    let controls = match read {
+        Ok(_) => std::str::from_utf8(&bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|text| KeyBindings::from_toml(text).map_err(|e| e.to_string())),
+        Err(e) => Err(e.to_string()),
+    };
+    controls.map_err(|e| {
+        let moved = ggez::filesystem::create(ctx, CORRUPT_CONTROLS_PATH)
+            .and_then(|mut file| Ok(file.write_all(&bytes)?));
+        let message = match moved {
+            Ok(()) => format!(
+                "Couldn't read {} ({}), so it was moved to {}",
+                CONTROLS_PATH, e, CORRUPT_CONTROLS_PATH
+            ),
+            Err(move_error) => format!(
+                "Couldn't read {} ({}) or set it aside ({})",
+                CONTROLS_PATH, e, move_error
+            ),
+        };
+        eprintln!("{}; using the default controls", message);
+        message
+    })
 }
 
-// This is again an abstraction over a `GridPosition` that represents
-// a piece of food the snake can eat. It can draw itself.
-struct Food {
-    pos: GridPosition,
+fn save_controls(ctx: &mut Context, controls: &KeyBindings) -> GameResult {
+    let text = controls
+        .to_toml()
+        .map_err(|e| ggez::GameError::FilesystemError(e.to_string()))?;
+    let mut file = ggez::filesystem::create(ctx, CONTROLS_PATH)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
 }
 
-impl Food {
-    pub fn new(pos: GridPosition) -> Self {
-        Food { pos }
-    }
-
-    // Here is the first time we see what drawing looks like with ggez.
-    // We have a function that takes in a `&mut ggez::Context` which we use
-    // with the helpers in `ggez::graphics` to do drawing. We also return a
-    // `ggez::GameResult` so that we can use the `?` operator to bubble up
-    // failure of drawing.
-    fn draw(&self, ctx: &mut Context) -> GameResult {
-        // First we have to create a MeshBuilder
-        let mesh = graphics::MeshBuilder::new()
-            // We call rectangle to make a square
-            .rectangle(
-                // Then we draw a rectangle with the Fill draw mode, and we convert the
-                graphics::DrawMode::fill(),
-                // since we implemented `From<GridPosition>` for `Rect` earlier.
-                // Food's position into a `ggez::Rect` using `.into()` which we can do
-                self.pos.into(),
-                // Last we set the color to draw with, in this case all food will be
-                // colored blue.
-                graphics::Color::new(0.0, 0.0, 1.0, 1.0),
-            )?
-            .build(ctx)?;
-
-        graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
-        Ok(())
+// Here we load the high score table from disk. A missing file just means nobody
+// has played yet, and a file we can't make sense of is set aside rather than
+// stopping the game from starting.
+fn load_high_scores(ctx: &mut Context) -> HighScoreTable {
+    if !ggez::filesystem::exists(ctx, HIGH_SCORES_PATH) {
+        return HighScoreTable::new();
+    }
+    // We read the raw bytes rather than text, so that a file that isn't even valid
+    // UTF-8 is still set aside whole.
+    let mut bytes = Vec::new();
+    let read = ggez::filesystem::open(ctx, HIGH_SCORES_PATH)
+        .and_then(|mut file| Ok(file.read_to_end(&mut bytes)?));
+    let table = match read {
+        Ok(_) => std::str::from_utf8(&bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|text| HighScoreTable::from_toml(text).map_err(|e| e.to_string())),
+        Err(e) => Err(e.to_string()),
+    };
+    match table {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!(
+                "Couldn't read high scores ({}); moving them to {}",
+                e, CORRUPT_HIGH_SCORES_PATH
+            );
+            if let Err(e) = ggez::filesystem::create(ctx, CORRUPT_HIGH_SCORES_PATH)
+                .and_then(|mut file| Ok(file.write_all(&bytes)?))
+            {
+                eprintln!("Couldn't set aside the old high scores: {}", e);
+            }
+            HighScoreTable::new()
+        }
     }
 }
 
-// Here we define an enum of the possible things that the snake could have "eaten"
-// during an update of the game. It could have either eaten a piece of `Food`, or
-// it could have eaten `Itself` if the head ran into its body.
-#[derive(Clone, Copy, Debug)]
-enum Ate {
-    Itself,
-    Food,
+// And here we write it back out again.
+fn save_high_scores(ctx: &mut Context, table: &HighScoreTable) -> GameResult {
+    let text = table
+        .to_toml()
+        .map_err(|e| ggez::GameError::FilesystemError(e.to_string()))?;
+    let mut file = ggez::filesystem::create(ctx, HIGH_SCORES_PATH)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
 }
 
-// Now we make a struct that contains all the information needed to describe the
-// state of the Snake itself.
-struct Snake {
-    // First we have the head of the snake, which is a single `Segment`.
-    head: Segment,
-    // Then we have the current direction the snake is moving. This is
-    // the direction it will move when `update` is called on it.
-    dir: Direction,
-    // Next we have the body, which we choose to represent as a `LinkedList`
-    // of `Segment`s.
-    body: LinkedList<Segment>,
-    // Now we have a property that represents the result of the last update
-    // that was performed. The snake could have eaten nothing (None), Food (Some(Ate::Food)),
-    // or Itself (Some(Ate::Itself))
-    ate: Option<Ate>,
-    // Finally we store the direction that the snake was traveling the last
-    // time that `update` was called, which we will use to determine valid
-    // directions that it could move the next time update is called.
-    last_update_dir: Direction,
+// The game over screen lists the high score table, one line per entry.
+fn format_high_scores(table: &HighScoreTable) -> String {
+    let mut lines = String::from("High scores:\n");
+    for (rank, entry) in table.entries().iter().enumerate() {
+        let seconds = entry.duration_secs;
+        lines.push_str(&format!(
+            "{:>2}. {:<12} {:>6}  length {:>4}  {:02}:{:02}  {}  seed {}\n",
+            rank + 1,
+            entry.name,
+            entry.score,
+            entry.length,
+            seconds / 60,
+            seconds % 60,
+            format_date(entry.date),
+            entry.seed,
+        ));
+    }
+    lines
 }
 
-impl Snake {
-    pub fn new(pos: GridPosition) -> Self {
-        let mut body = LinkedList::new();
-        // Our snake will initially have a head and one body segment,
-        // and will be moving to the right.
-        body.push_back(Segment::new((pos.x - 1, pos.y).into()));
-        Snake {
-            head: Segment::new(pos),
-            dir: Direction::Right,
-            last_update_dir: Direction::Right,
-            body,
-            ate: None,
-        }
-    }
-
-    // A helper function that determines whether
-    // the snake eats a given piece of Food based
-    // on its current position
-    fn eats(&self, food: &Food) -> bool {
-        self.head.pos == food.pos
-    }
-
-    // A helper function that determines whether
-    // the snake eats itself based on its current position
-    fn eats_self(&self) -> bool {
-        for seg in self.body.iter() {
-            if self.head.pos == seg.pos {
-                return true;
-            }
-        }
-        false
-    }
-
-    // The main update function for our snake which gets called every time
-    // we want to update the game state.
-    fn update(&mut self, food: &Food) {
-        // First we get a new head position by using our `new_from_move` helper
-        // function from earlier. We move our head in the direction we are currently
-        // heading.
-        let new_head_pos = GridPosition::new_from_move(self.head.pos, self.dir);
-        // Next we create a new segment will be our new head segment using the
-        // new position we just made.
-        let new_head = Segment::new(new_head_pos);
-        // Then we push our current head Segment onto the front of our body
-        self.body.push_front(self.head);
-        // And finally make our actual head the new Segment we created. This has
-        // effectively moved the snake in the current direction.
-        self.head = new_head;
-        // Next we check whether the snake eats itself or some food, and if so,
-        // we set our `ate` member to reflect that state.
-        if self.eats_self() {
-            self.ate = Some(Ate::Itself);
-        } else if self.eats(food) {
-            self.ate = Some(Ate::Food);
-        } else {
-            self.ate = None
-        }
-        // If we didn't eat anything this turn, we remove the last segment from our body,
-        // which gives the illusion that the snake is moving. In reality, all the segments stay
-        // stationary, we just add a segment to the front and remove one from the back. If we eat
-        // a piece of food, then we leave the last segment so that we extend our body by one.
-        if self.ate.is_none() {
-            self.body.pop_back();
-        }
-        // And set our last_update_dir to the direction we just moved.
-        self.last_update_dir = self.dir;
-    }
-
-    // Here we have the Snake draw itself. This is very similar to how we saw the Food
-    // draw itself earlier.
-    fn draw(&self, ctx: &mut Context) -> GameResult {
-        // We first iterate through the body segments and draw them.
-        for seg in self.body.iter() {
-            // First we create a new MeshBuilder
-            let mesh = graphics::MeshBuilder::new()
-                // Since we want a square we call rectangle method
-                .rectangle(
-                    // Then set DrawMode to fill the rectangle
-                    graphics::DrawMode::fill(),
-                    // We use `.into` (provided by the rust `Into` trait) to convert our position to the `mint` type that ggez's api wants
-                    seg.pos.into(),
-                    // Again we set the color (in this case an orangey color)
-                    graphics::Color::new(1.0, 0.5, 0.0, 1.0),
-                )?
-                .build(ctx)?;
-            graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
-        }
-        // And then we do the same for the head, instead making it fully red to distinguish it.
-
-        // And then we do the same for the head, instead making it fully red to distinguish it
-        let mesh = graphics::MeshBuilder::new()
-            .rectangle(
-                graphics::DrawMode::fill(),
-                self.head.pos.into(),
-                graphics::Color::new(1.0, 0.0, 0.0, 1.0),
-            )?
-            .build(ctx)?;
-
-        graphics::draw(ctx, &mesh, graphics::DrawParam::default())?;
-        Ok(())
-    }
+// Our game moves between a handful of scenes, each of which reacts to keys and
+// draws itself in its own way:
+//
+// Title -> Playing <-> Paused
+//   |  \      |          |
+//   |   \     v          |
+//   |    \ GameOver -> Playing (restart, or the next round of a match)
+//   v     v
+// Editor  Controls
+//   ^
+//   v
+// Playing (test play)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scene {
+    // The screen we show when the game first starts.
+    Title,
+    // The snake is moving and the player is in control.
+    Playing,
+    // The game is frozen until the player unpauses it.
+    Paused,
+    // The snake has died or filled the board, or a round of a match is over; we
+    // show how it went.
+    GameOver,
+    // The player is drawing a level of their own.
+    Editor,
+    // The player is choosing which keys do what.
+    Controls,
 }
 
-// Now we have the heart of our game, the GameState. This struct
+// Now we have the heart of our frontend, the GameState. This struct
 // will implement ggez's `EventHandler` trait and will therefore drive
 // everything else that happens in our game.
 struct GameState {
-    // First we need a Snake
-    snake: Snake,
-    // A piee of food
-    food: Food,
-    // Whether the game is over or not
-    gameover: bool,
-    // And we track the last time we updated so that we can limit
-    // our update rate.
-    last_update: Instant,
+    // First we need the simulation that holds the actual game
+    sim: Simulation,
+    // The config that decides the board size, cell size and speed of every game
+    config: GameConfig,
+    // The difficulty picked on the title screen
+    difficulty: DifficultyPreset,
+    // Every level the player can pick from, and the one they've picked
+    levels: Vec<Level>,
+    level: usize,
+    // How many players are picked on the title screen, and while a match between
+    // more than one of them is being played, how it's going
+    players: usize,
+    versus: Option<VersusMatch>,
+    // Which scene we are currently showing
+    scene: Scene,
+    // The seed we were asked to play with on the command line, if any. When
+    // it's set, every new game replays that same seed.
+    seed: Option<u64>,
+    // Which keys do what, and in the controls menu, which binding is picked, whether
+    // we're waiting for a key to bind to it, and anything the menu has to say
+    controls: KeyBindings,
+    control_row: usize,
+    listening: bool,
+    controls_status: String,
+    // How each player has asked to steer since the last update, and whether the
+    // speed boost key is being held down
+    inputs: Vec<Vec<Steer>>,
+    boosting: bool,
+    // Which player each controller belongs to, along with where its stick is pushed
+    controllers: Controllers<GamepadId>,
+    // How long the current game has been played for, not counting time spent paused
+    elapsed: Duration,
+    // The best score we know of, either from the high score table or from a
+    // game played since we started up
+    best_score: u32,
+    // The high score table, as loaded from disk
+    high_scores: HighScoreTable,
+    // While the player is typing their name for the high score table, this
+    // holds what they've typed so far.
+    name_entry: Option<String>,
+    // The rectangles that make up the board, which we keep around so that we
+    // don't have to allocate a new list of them every frame.
+    quads: Vec<Quad>,
+    // The tileset to draw the snake and food with, if we have one, and the sprites
+    // we draw from it, again kept around from frame to frame.
+    tileset: Option<graphics::Image>,
+    sprites: Vec<Sprite>,
+    // Every theme the player can switch between, and which one we're drawing with.
+    themes: Vec<Theme>,
+    theme: usize,
+    // The level editor, the tile the player is painting with, and whatever the
+    // editor has to tell them, like why their level can't be played yet, along with
+    // the cell that's to blame, if there is one.
+    editor: LevelEditor,
+    brush: MapTile,
+    editor_status: String,
+    editor_highlight: Option<GridPosition>,
+    // While a level from the editor is being test-played, this is the level.
+    // Leaving the game then goes back to the editor instead of quitting.
+    test_level: Option<Level>,
+    // And we turn the time between frames into a steady stream of
+    // simulation ticks.
+    timestep: FixedTimestep,
 }
 
 impl GameState {
-    // Our new function will set up the initial state of our game.
-    pub fn new() -> GameResult<Self> {
-        // First we put our snake a quarter of the way across our grid in the x axis
-        // and half way down the y axis. This works well since we start out moving to the right.
-        let snake_pos = (GRID_SIZE.0 / 4, GRID_SIZE.1 / 2).into();
-        // Then we choose a random place to put our piece of food using the helper we made
-        // earlier.
-        let food_pos = GridPosition::random(GRID_SIZE.0, GRID_SIZE.1);
-
+    // Our new function will set up the initial state of our game, starting out
+    // on the title screen.
+    pub fn new(ctx: &mut Context, options: &Options) -> GameResult<Self> {
+        let high_scores = load_high_scores(ctx);
+        let (controls, controls_status) = match load_controls(ctx) {
+            Ok(controls) => (controls, String::new()),
+            Err(e) => (KeyBindings::default(), e),
+        };
+        let tileset = load_tileset(ctx, &options.config);
+        let (themes, theme) = load_themes(ctx, &options.config)?;
+        let (levels, level) = load_levels(ctx, &options.config)?;
+        let sim = GameState::new_simulation(
+            &options.config,
+            options.config.difficulty,
+            &levels[level],
+            1,
+            options.seed,
+        );
+        fit_screen_to_board(
+            ctx,
+            sim.board().width as usize,
+            sim.board().height as usize,
+            options.config.cell_size,
+        );
+        let editor = LevelEditor::new(LevelMap::from_level(&levels[level]));
         Ok(GameState {
-            snake: Snake::new(snake_pos),
-            food: Food::new(food_pos),
-            gameover: false,
-            last_update: Instant::now(),
+            sim,
+            config: options.config.clone(),
+            difficulty: options.config.difficulty,
+            levels,
+            level,
+            players: options.config.players,
+            versus: None,
+            scene: Scene::Title,
+            seed: options.seed,
+            controls,
+            control_row: 0,
+            listening: false,
+            controls_status,
+            inputs: Vec::new(),
+            boosting: false,
+            controllers: Controllers::new(options.config.gamepad_deadzone),
+            elapsed: Duration::ZERO,
+            best_score: high_scores.best(),
+            high_scores,
+            name_entry: None,
+            quads: Vec::new(),
+            tileset,
+            sprites: Vec::new(),
+            themes,
+            theme,
+            editor,
+            brush: BRUSHES[0],
+            editor_status: String::new(),
+            editor_highlight: None,
+            test_level: None,
+            timestep: FixedTimestep::new(),
         })
     }
+
+    // If we weren't given a seed we just pick a random one for every game.
+    fn new_simulation(
+        config: &GameConfig,
+        difficulty: DifficultyPreset,
+        level: &Level,
+        players: usize,
+        seed: Option<u64>,
+    ) -> Simulation {
+        Simulation::versus(level.clone(), players, seed.unwrap_or_else(rand::random))
+            .with_difficulty(level.difficulty(config.difficulty_curve(difficulty)))
+            .with_food_count(config.food_count as usize)
+    }
+
+    // Throws away whatever game was going on and starts a fresh one, on the level
+    // being test-played if there is one, or else the one picked on the title screen.
+    // In a match, this starts its next round.
+    fn start_game(&mut self, ctx: &mut Context) {
+        let level = self.test_level.as_ref().unwrap_or(&self.levels[self.level]);
+        self.sim = GameState::new_simulation(
+            &self.config,
+            self.difficulty,
+            level,
+            self.players,
+            self.seed,
+        );
+        let board = self.sim.board();
+        fit_screen_to_board(
+            ctx,
+            board.width as usize,
+            board.height as usize,
+            self.config.cell_size,
+        );
+        self.inputs = vec![Vec::new(); self.sim.players()];
+        self.boosting = false;
+        self.elapsed = Duration::ZERO;
+        self.name_entry = None;
+        self.timestep.reset();
+        self.scene = Scene::Playing;
+    }
+
+    // Starts a game, or with more than one player, a whole new match.
+    fn start_match(&mut self, ctx: &mut Context) {
+        self.versus = match self.players {
+            1 => None,
+            players => Some(VersusMatch::new(players, self.config.rounds_to_win)),
+        };
+        self.start_game(ctx);
+    }
+
+    // After a round of a match, we start the next one, or once somebody has won
+    // the match, a new match.
+    fn next_round(&mut self, ctx: &mut Context) {
+        match &self.versus {
+            Some(versus) if !versus.is_over() => self.start_game(ctx),
+            _ => self.start_match(ctx),
+        }
+    }
+
+    // When a game ends, we check whether it earned a place in the high score
+    // table, and if so, ask the player for their name.
+    // Test plays from the editor and rounds of a match don't count. A round
+    // instead counts towards the match.
+    fn end_game(&mut self) {
+        self.scene = Scene::GameOver;
+        if let (Some(versus), Some(outcome)) = (&mut self.versus, self.sim.outcome()) {
+            match versus.record(outcome) {
+                Ok(()) => return,
+                // A level too crowded for more than one snake is played on our
+                // own, and a game like that can't count towards the match.
+                Err(e) => {
+                    eprintln!("Couldn't count the round: {}", e);
+                    self.versus = None;
+                }
+            }
+        }
+        if self.test_level.is_none() && self.high_scores.qualifies(self.sim.score().points()) {
+            self.name_entry = Some(String::new());
+        }
+    }
+
+    // Leaving a game quits, unless it's a test play from the editor, in which case we
+    // go back to editing.
+    fn leave_game(&mut self, ctx: &mut Context) {
+        if self.test_level.take().is_some() {
+            self.show_editor(ctx);
+        } else {
+            event::quit(ctx);
+        }
+    }
+
+    // Opens the editor on the level picked on the title screen.
+    fn open_editor(&mut self, ctx: &mut Context) {
+        self.editor = LevelEditor::new(LevelMap::from_level(&self.levels[self.level]));
+        self.editor_status = format!("Editing {}", self.editor.map().name);
+        self.editor_highlight = None;
+        self.show_editor(ctx);
+    }
+
+    fn show_editor(&mut self, ctx: &mut Context) {
+        let map = self.editor.map();
+        fit_screen_to_board(ctx, map.width(), map.height(), self.config.cell_size);
+        self.scene = Scene::Editor;
+    }
+
+    // Plays the level in the editor, if it can be played. If it can't, we say why
+    // and point at the cell that's to blame.
+    fn test_play(&mut self, ctx: &mut Context) {
+        match self.editor.map().to_level() {
+            Ok(level) => {
+                self.test_level = Some(level);
+                self.start_match(ctx);
+            }
+            Err(e) => {
+                self.editor_highlight = self.editor.map().error_cell(&e);
+                self.editor_status = format!("Can't play this level yet: {}", e.kind);
+            }
+        }
+    }
+
+    // Saves the level in the editor. A level that can't be played yet is still
+    // saved, so it can be finished later, but we let the player know.
+    fn save_level(&mut self, ctx: &mut Context) {
+        let text = self.editor.map().to_text();
+        let saved = ggez::filesystem::create_dir(ctx, "/levels")
+            .and_then(|_| ggez::filesystem::create(ctx, EDITOR_LEVEL_PATH))
+            .and_then(|mut file| Ok(file.write_all(text.as_bytes())?));
+        self.editor_status = match (saved, self.editor.map().to_level()) {
+            (Err(e), _) => format!("Couldn't save to {}: {}", EDITOR_LEVEL_PATH, e),
+            (Ok(()), Ok(_)) => format!("Saved to {}", EDITOR_LEVEL_PATH),
+            (Ok(()), Err(e)) => format!(
+                "Saved to {}, but it can't be played yet: {}",
+                EDITOR_LEVEL_PATH, e.kind
+            ),
+        };
+    }
+
+    // Loads the level the editor last saved. Loading can be undone like any other
+    // change, in case it was pressed by mistake.
+    fn load_level(&mut self, ctx: &mut Context) {
+        let mut text = String::new();
+        let read = ggez::filesystem::open(ctx, EDITOR_LEVEL_PATH)
+            .and_then(|mut file| Ok(file.read_to_string(&mut text)?));
+        self.editor_highlight = None;
+        self.editor_status = match read.map(|_| LevelMap::parse(&text)) {
+            Err(e) => format!("Couldn't read {}: {}", EDITOR_LEVEL_PATH, e),
+            Ok(Err(e)) => format!("Couldn't load {}: {}", EDITOR_LEVEL_PATH, e),
+            Ok(Ok(map)) => {
+                self.editor.edit(|old| *old = map);
+                format!("Loaded {}", EDITOR_LEVEL_PATH)
+            }
+        };
+        self.show_editor(ctx);
+    }
+
+    // What each key does in the editor. Undo, redo, saving and loading are held
+    // with Ctrl, the way they are everywhere else.
+    fn editor_key(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods) {
+        let ctrl = keymod.contains(KeyMods::CTRL);
+        let shift = keymod.contains(KeyMods::SHIFT);
+        match keycode {
+            KeyCode::Z if ctrl && shift => {
+                self.editor.redo();
+            }
+            KeyCode::Z if ctrl => {
+                self.editor.undo();
+            }
+            KeyCode::Y if ctrl => {
+                self.editor.redo();
+            }
+            KeyCode::S if ctrl => self.save_level(ctx),
+            KeyCode::O if ctrl => self.load_level(ctx),
+            KeyCode::Key1 => self.brush = BRUSHES[0],
+            KeyCode::Key2 => self.brush = BRUSHES[1],
+            KeyCode::Key3 => self.brush = BRUSHES[2],
+            KeyCode::Key4 => self.brush = BRUSHES[3],
+            KeyCode::Key5 => self.brush = BRUSHES[4],
+            KeyCode::D => self
+                .editor
+                .edit(|map| map.direction = map.direction.clockwise()),
+            KeyCode::E => self.editor.edit(|map| map.edges = next_edges(map.edges)),
+            KeyCode::Return => self.test_play(ctx),
+            KeyCode::Escape => self.scene = Scene::Title,
+            _ => (),
+        }
+    }
+
+    // Draws the map being edited, with what the editor has to say on top.
+    fn draw_editor(&mut self, ctx: &mut Context) -> GameResult {
+        editor_quads(
+            self.editor.map(),
+            &self.themes[self.theme],
+            self.config.cell_size as f32,
+            self.editor_highlight,
+            &mut self.quads,
+        );
+        draw_quads(ctx, &self.quads)?;
+        let map = self.editor.map();
+        // Undo and redo are greyed out while there's nothing to undo or redo.
+        let hud = self.theme().hud;
+        let shown_if = |enabled: bool| {
+            let mut color = hud;
+            if !enabled {
+                color[3] *= 0.4;
+            }
+            graphics::Color::from(color)
+        };
+        let mut text = graphics::Text::new(format!(
+            "{}   Brush: {} (1-5)   Start: {} (D)   Edges: {} (E)\nLeft click paints, right click erases   ",
+            map.name, self.brush, map.direction, map.edges
+        ));
+        text.add(
+            graphics::TextFragment::new("Ctrl+Z undo").color(shown_if(self.editor.can_undo())),
+        );
+        text.add("   ");
+        text.add(
+            graphics::TextFragment::new("Ctrl+Y redo").color(shown_if(self.editor.can_redo())),
+        );
+        text.add(format!(
+            "   Ctrl+S/Ctrl+O save/load\nEnter to play   Escape to leave\n{}",
+            self.editor_status
+        ));
+        graphics::draw(
+            ctx,
+            &text,
+            graphics::DrawParam::default()
+                .dest([8.0, 8.0])
+                .color(self.theme().hud.into()),
+        )?;
+        Ok(())
+    }
+
+    // Once the player has typed their name, we add the game to the high score
+    // table and save it. Failing to save is annoying but not worth crashing over.
+    fn submit_high_score(&mut self, ctx: &mut Context) {
+        let name = match self.name_entry.take() {
+            Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+            Some(_) => "Anonymous".to_string(),
+            None => return,
+        };
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        self.high_scores.insert(HighScore {
+            name,
+            score: self.sim.score().points(),
+            length: self.sim.snake().length() as u32,
+            duration_secs: self.elapsed.as_secs(),
+            seed: self.sim.seed(),
+            date,
+        });
+        if let Err(e) = save_high_scores(ctx, &self.high_scores) {
+            eprintln!("Couldn't save high scores: {}", e);
+        }
+    }
+
+    // While playing, we step the simulation at our fixed update rate.
+    fn update_playing(&mut self, ctx: &Context) {
+        // First we add however long the last frame took to our timestep, and to the
+        // time the game has been played for. That's the real time, which isn't the
+        // same as the time the updates stand for while the speed boost is held.
+        let delta = ggez::timer::delta(ctx);
+        self.timestep.advance(delta);
+        self.elapsed += delta;
+        // Then we step the simulation once for every whole update interval that has
+        // built up, which may be more than once if the last frame was slow. How long
+        // an interval is depends on the difficulty, and gets shorter as the snake eats,
+        // so we ask for it again before every step.
+        loop {
+            let interval = self.update_interval();
+            if !self.timestep.tick(interval) {
+                break;
+            }
+            // We hand the simulation everything the players pressed since the last
+            // step. The simulation takes care of moving the snakes, eating food and
+            // ending the game.
+            let inputs: Vec<&[Steer]> = self.inputs.iter().map(Vec::as_slice).collect();
+            self.sim.step_players(&inputs);
+            self.inputs.iter_mut().for_each(Vec::clear);
+            if self.versus.is_none() {
+                self.best_score = self.best_score.max(self.sim.score().points());
+            }
+            // If the game ended during this step, we move on to the game over scene.
+            if self.sim.is_over() {
+                self.end_game();
+                break;
+            }
+        }
+    }
+
+    // What we show at the end of a round of a match: who won the round, how many
+    // rounds each player has won, and who won the match, if anyone has yet.
+    fn versus_message(&self, leave: &str) -> String {
+        let versus = match &self.versus {
+            Some(versus) => versus,
+            None => return String::new(),
+        };
+        let round = match self.sim.outcome() {
+            Some(Outcome::Winner(player)) => format!("Player {} wins the round!", player + 1),
+            _ => "Nobody wins the round!".to_string(),
+        };
+        let mut tally: Vec<String> = versus
+            .wins()
+            .iter()
+            .enumerate()
+            .map(|(player, wins)| format!("Player {}: {}", player + 1, wins))
+            .collect();
+        if versus.draws() > 0 {
+            tally.push(format!("Draws: {}", versus.draws()));
+        }
+        let next = match versus.winner() {
+            Some(player) => format!(
+                "Player {} wins the match!\n\nPress Enter or {} for a new match",
+                player + 1,
+                self.keys_for_except(Action::Restart, Some(KeyCode::Return))
+            ),
+            None => format!(
+                "First to {} wins the match\n\nPress Enter for the next round\nPress {} to start the match over",
+                versus.rounds_to_win(),
+                self.keys_for_except(Action::Restart, Some(KeyCode::Return))
+            ),
+        };
+        format!(
+            "{}\n\n{}\n{}\n{}\nSeed: {}",
+            round,
+            tally.join("   "),
+            next,
+            leave,
+            self.sim.seed()
+        )
+    }
+
+    // How long to wait between updates, which is shorter while the speed boost is on.
+    fn update_interval(&self) -> Duration {
+        match self.boosting {
+            true => self.sim.update_interval() / SPEED_BOOST,
+            false => self.sim.update_interval(),
+        }
+    }
+
+    // The keys bound to an action, written out for the player, with every player's
+    // keys together since any of them will do.
+    fn keys_for(&self, action: Action) -> String {
+        self.keys_for_except(action, None)
+    }
+
+    // The same, leaving out a key that does something else on the screen we're
+    // showing them on.
+    fn keys_for_except(&self, action: Action, except: Option<KeyCode>) -> String {
+        let except = except.map(key_name);
+        let keys: Vec<&str> = (0..self.controls.players())
+            .flat_map(|player| self.controls.keys(player, action))
+            .map(String::as_str)
+            .filter(|&key| Some(key) != except)
+            .collect();
+        match keys.as_slice() {
+            [] => "(unbound)".to_string(),
+            keys => keys.join("/"),
+        }
+    }
+
+    // Does whatever a bound key's action means in the scene we're in, and returns
+    // whether it meant anything. Playing on our own, any player's keys steer our
+    // snake, each the way that player has picked.
+    fn game_action(&mut self, ctx: &mut Context, player: usize, action: Action) -> bool {
+        match (self.scene, action) {
+            (Scene::Playing, Action::Turn(dir)) => {
+                // We hold on to the way to steer until the next update, when the
+                // simulation will decide whether the snake is allowed to turn that way.
+                let steer = self.controls.mode(player).steer(dir);
+                let player = player.min(self.inputs.len().saturating_sub(1));
+                if let (Some(inputs), Some(steer)) = (self.inputs.get_mut(player), steer) {
+                    inputs.push(steer);
+                }
+            }
+            (Scene::Playing, Action::Pause) => self.scene = Scene::Paused,
+            (Scene::Playing, Action::SpeedBoost) => self.boosting = true,
+            (Scene::Paused, Action::Pause) => self.scene = Scene::Playing,
+            (Scene::Playing, Action::Restart)
+            | (Scene::Paused, Action::Restart)
+            | (Scene::GameOver, Action::Restart) => self.start_match(ctx),
+            (Scene::Playing, Action::Quit)
+            | (Scene::Paused, Action::Quit)
+            | (Scene::GameOver, Action::Quit) => self.leave_game(ctx),
+            _ => return false,
+        }
+        true
+    }
+
+    // Every row the controls menu lists, as the player and the action, with a row for
+    // how each player steers, which has no action, ahead of their bindings.
+    fn control_rows(&self) -> Vec<(usize, Option<Action>)> {
+        (0..self.controls.players())
+            .flat_map(|player| {
+                std::iter::once(None)
+                    .chain(Action::ALL.iter().copied().map(Some))
+                    .map(move |action| (player, action))
+            })
+            .collect()
+    }
+
+    // What each key does in the controls menu. The menu's own keys can't be
+    // rebound, so there's always a way back out of it.
+    fn controls_key(&mut self, ctx: &mut Context, keycode: KeyCode) {
+        let rows = self.control_rows();
+        let (player, row) = rows[self.control_row];
+        // On a player's steering row, Enter switches how they steer.
+        let action = match row {
+            Some(action) => action,
+            None => {
+                match keycode {
+                    KeyCode::Return => {
+                        let mode = match self.controls.mode(player) {
+                            ControlMode::Absolute => ControlMode::Relative,
+                            ControlMode::Relative => ControlMode::Absolute,
+                        };
+                        self.controls.set_mode(player, mode);
+                    }
+                    KeyCode::Back => (),
+                    _ => self.controls_menu_key(ctx, keycode, rows.len()),
+                }
+                return;
+            }
+        };
+        // While we're waiting for a key, Escape gives up on binding one, so Escape
+        // itself can only be bound in the file.
+        if self.listening {
+            self.listening = false;
+            self.controls_status = match keycode {
+                KeyCode::Escape => String::new(),
+                _ => match self.controls.bind(player, action, key_name(keycode)) {
+                    Ok(()) => String::new(),
+                    Err(conflict) => format!(
+                        "{} is already bound to player {} {}",
+                        conflict.key,
+                        conflict.first.0 + 1,
+                        conflict.first.1
+                    ),
+                },
+            };
+            return;
+        }
+        match keycode {
+            KeyCode::Return => {
+                self.listening = true;
+                self.controls_status.clear();
+            }
+            KeyCode::Back => self.controls.clear(player, action),
+            _ => self.controls_menu_key(ctx, keycode, rows.len()),
+        }
+    }
+
+    // The keys that do the same on every row of the controls menu.
+    fn controls_menu_key(&mut self, ctx: &mut Context, keycode: KeyCode, rows: usize) {
+        match keycode {
+            KeyCode::Up => self.control_row = (self.control_row + rows - 1) % rows,
+            KeyCode::Down => self.control_row = (self.control_row + 1) % rows,
+            KeyCode::Delete => {
+                self.controls = KeyBindings::default();
+                self.controls_status = "Restored the default controls".to_string();
+            }
+            KeyCode::Escape => {
+                if let Err(e) = save_controls(ctx, &self.controls) {
+                    eprintln!("Couldn't save controls: {}", e);
+                }
+                self.controls_status.clear();
+                self.scene = Scene::Title;
+            }
+            _ => (),
+        }
+    }
+
+    // Lists every binding, with the picked one marked.
+    fn draw_controls(&mut self, ctx: &mut Context) -> GameResult {
+        let mut text = String::from("Controls\n\n");
+        for (row, (player, action)) in self.control_rows().into_iter().enumerate() {
+            let marker = if row == self.control_row { ">" } else { " " };
+            let line = match action {
+                None => format!("steering: {}", self.controls.mode(player)),
+                Some(_) if row == self.control_row && self.listening => {
+                    "press a key...".to_string()
+                }
+                Some(action) => format!(
+                    "{}: {}",
+                    action,
+                    self.controls.keys(player, action).join(", ")
+                ),
+            };
+            text.push_str(&format!("{} Player {} {}\n", marker, player + 1, line));
+        }
+        text.push_str(&format!(
+            "\nUp/Down to pick   Enter to add a key or switch steering   Backspace to clear   Delete to restore the defaults\nEscape to save and leave\n{}",
+            self.controls_status
+        ));
+        graphics::draw(
+            ctx,
+            &graphics::Text::new(text),
+            graphics::DrawParam::default()
+                .dest([8.0, 8.0])
+                .color(self.theme().hud.into()),
+        )?;
+        Ok(())
+    }
+
+    fn theme(&self) -> &Theme {
+        &self.themes[self.theme]
+    }
+
+    // How far we are between one update and the next, which we use to draw the
+    // snake moving smoothly. Once the game is over, the snake has stopped moving,
+    // so we draw it exactly where it ended up.
+    fn alpha(&self) -> f32 {
+        match self.scene {
+            Scene::GameOver => 1.0,
+            _ => self.timestep.alpha(self.update_interval()),
+        }
+    }
+
+    // Draws the board itself, the snake and the food, with the HUD on top.
+    fn draw_board(&mut self, ctx: &mut Context) -> GameResult {
+        let cell_size = self.config.cell_size as f32;
+        let alpha = self.alpha();
+        match &self.tileset {
+            // With a tileset, only the walls and obstacles are drawn as rectangles.
+            Some(tileset) => {
+                self.quads.clear();
+                terrain_quads(
+                    self.sim.board(),
+                    &self.themes[self.theme],
+                    cell_size,
+                    &mut self.quads,
+                );
+                draw_quads(ctx, &self.quads)?;
+                board_sprites(
+                    &self.sim,
+                    &self.themes[self.theme],
+                    cell_size,
+                    alpha,
+                    &mut self.sprites,
+                );
+                draw_sprites(ctx, tileset, &self.sprites)?;
+            }
+            None => {
+                board_quads(
+                    &self.sim,
+                    &self.themes[self.theme],
+                    cell_size,
+                    alpha,
+                    &mut self.quads,
+                );
+                draw_quads(ctx, &self.quads)?;
+            }
+        }
+        draw_hud(
+            ctx,
+            &self.sim,
+            self.versus.as_ref(),
+            self.elapsed,
+            self.best_score,
+            self.theme().hud.into(),
+        )?;
+        Ok(())
+    }
 }
 
 // Now we implement EventHandler for GameState. This provides an interface
@@ -383,34 +1429,11 @@ impl GameState {
 impl event::EventHandler<ggez::GameError> for GameState {
     // Update will happen on every frame before it is drawn. This is where we update
     // our game state to react to whatever is happening in the game world.
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        // First we check to see if enough time has elapsed since our last update based on
-        // the update rate we defined at the top.
-        if Instant::now() - self.last_update >= Duration::from_millis(MILLIS_PER_UPDATE) {
-            // Then we check to see if the game is over. If not, we'll update. If so, we'll just do nothing.
-            if !self.gameover {
-                // Here we do the actual updating of our game world. First we tell the snake to update itself,
-                // passing in a reference to our piece of food.
-                self.snake.update(&self.food);
-                // Next we check if the snake ate anything as it updated.
-                if let Some(ate) = self.snake.ate {
-                    // If it did, we want to know what it ate.
-                    match ate {
-                        // If it ate a piece of food, we randomly select a new position for our piece of food
-                        // and move it to this new position.
-                        Ate::Food => {
-                            let new_food_pos = GridPosition::random(GRID_SIZE.0, GRID_SIZE.1);
-                            self.food.pos = new_food_pos;
-                        }
-                        // If it ate itself, we set our gameover state to true.
-                        Ate::Itself => {
-                            self.gameover = true;
-                        }
-                    }
-                }
-            }
-            // If we updated, we set our last_update to be now
-            self.last_update = Instant::now();
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        // Only the playing scene has anything to update; every other scene is
+        // just waiting for the player to press a key.
+        if self.scene == Scene::Playing {
+            self.update_playing(ctx);
         }
         // Finally we return `Ok` to indicate we didn't run into any errors
         Ok(())
@@ -418,51 +1441,280 @@ impl event::EventHandler<ggez::GameError> for GameState {
 
     // draw is where we should actually render the game's current state.
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        // First we clear the screen and
-        // We set the background color to a nice (well, maybe pretty glaring ;)) green
-        graphics::clear(ctx, [0.0, 1.0, 0.0, 1.0].into());
-        // Then we tell the snake and the food to draw themselves
-        self.snake.draw(ctx)?;
-        self.food.draw(ctx)?;
+        // First we clear the screen to the background color of our theme, which in
+        // the classic theme is a nice (well, maybe pretty glaring ;)) green
+        let text_color = self.theme().hud.into();
+        graphics::clear(ctx, self.theme().background.into());
+        // Q leaves a test play from the editor rather than quitting the game.
+        let quit = self.keys_for(Action::Quit);
+        let leave = if self.test_level.is_some() {
+            format!("Press {} to go back to the editor", quit)
+        } else {
+            format!("Press {} to quit", quit)
+        };
+        let leave = leave.as_str();
+        // Then we draw whatever the current scene calls for.
+        match self.scene {
+            Scene::Title => {
+                draw_centered_text(
+                    ctx,
+                    &format!(
+                        "Snake!\n\nDifficulty: < {} >\nLevel: < {} >\nPlayers: {}\nTheme: {}\n\nPress Left/Right to change difficulty\nPress Up/Down to change level\nPress V to change the number of players\nPress E to edit the level\nPress C to change the controls\nPress T to change theme\nPress Enter to start\nPress Escape to quit",
+                        self.difficulty,
+                        self.levels[self.level].name,
+                        self.players,
+                        self.theme().name
+                    ),
+                    text_color,
+                )?;
+            }
+            Scene::Playing => self.draw_board(ctx)?,
+            Scene::Editor => self.draw_editor(ctx)?,
+            Scene::Controls => self.draw_controls(ctx)?,
+            Scene::Paused => {
+                self.draw_board(ctx)?;
+                draw_centered_text(
+                    ctx,
+                    &format!(
+                        "Paused\n\nPress {} to resume\nPress {} to restart\n{}",
+                        self.keys_for(Action::Pause),
+                        self.keys_for(Action::Restart),
+                        leave
+                    ),
+                    text_color,
+                )?;
+            }
+            // If the game is over, we tell the player how it ended, along with the
+            // seed they can use to replay the same game. Then we either ask for their
+            // name, if they made the high score table, or show them the table.
+            // After a round of a match, we say who won it and how the match stands.
+            Scene::GameOver if self.versus.is_some() => {
+                self.draw_board(ctx)?;
+                let message = self.versus_message(leave);
+                draw_centered_text(ctx, &message, text_color)?;
+            }
+            Scene::GameOver => {
+                self.draw_board(ctx)?;
+                let message = match self.sim.outcome() {
+                    Some(Outcome::Won) => "You win!",
+                    _ => "Game over!",
+                };
+                let prompt = match &self.name_entry {
+                    Some(name) => format!(
+                        "New high score! Enter your name: {}_\n\nPress Enter when done\nPress Escape to skip",
+                        name
+                    ),
+                    None => format!(
+                        "{}\nPress {} to restart\n{}",
+                        format_high_scores(&self.high_scores),
+                        self.keys_for(Action::Restart),
+                        leave
+                    ),
+                };
+                draw_centered_text(
+                    ctx,
+                    &format!("{}\nSeed: {}\n\n{}", message, self.sim.seed(), prompt),
+                    text_color,
+                )?;
+            }
+        }
         // Finally we call graphics::present to cycle the gpu's framebuffer and display
         // the new frame we just drew.
         graphics::present(ctx)?;
-        // We yield the current thread until the next update
-        ggez::timer::yield_now();
         // And return success.
         Ok(())
     }
 
-    // key_down_event gets fired when a key gets pressed.
+    // key_down_event gets fired when a key gets pressed. What each key does
+    // depends on which scene we're in.
     fn key_down_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         keycode: KeyCode,
-        _keymod: ggez::input::keyboard::KeyMods,
+        keymod: KeyMods,
         _repeat: bool,
     ) {
-        // Here we attempt to convert the KeyCode into a Direction using the helper
-        // we defined earlier.
-        if let Some(dir) = Direction::from_keycode(keycode) {
-            // If it succeeds, we check to make sure that the direction being pressed
-            // is not directly opposite to the way the snake was facing last update.
-            if dir.inverse() != self.snake.last_update_dir {
-                // If not, we set the snake's new direction to be the direction the user pressed.
-                self.snake.dir = dir;
+        // While the player is typing their name, the keys that would normally
+        // restart or quit the game are used for editing the name instead.
+        if let Some(name) = &mut self.name_entry {
+            match keycode {
+                KeyCode::Return | KeyCode::NumpadEnter => self.submit_high_score(ctx),
+                KeyCode::Back => {
+                    name.pop();
+                }
+                KeyCode::Escape => self.name_entry = None,
+                _ => (),
+            }
+            return;
+        }
+        // The controls menu has every key to itself, so that any of them can be bound.
+        if self.scene == Scene::Controls {
+            self.controls_key(ctx, keycode);
+            return;
+        }
+        // After a round of a match, Enter goes on to the next round, as the screen
+        // says, even though by default it's one of the keys that restarts.
+        if self.scene == Scene::GameOver && self.versus.is_some() && keycode == KeyCode::Return {
+            self.next_round(ctx);
+            return;
+        }
+        // While a game is on, the key bindings come first.
+        if let Some((player, action)) = self.controls.action(key_name(keycode)) {
+            if self.game_action(ctx, player, action) {
+                return;
+            }
+        }
+        match (self.scene, keycode) {
+            // Whatever we're doing, T switches to the next theme.
+            (_, KeyCode::T) => self.theme = (self.theme + 1) % self.themes.len(),
+            // From the title screen we can start a game or quit.
+            (Scene::Title, KeyCode::Return) | (Scene::Title, KeyCode::Space) => {
+                self.start_match(ctx)
+            }
+            (Scene::Title, KeyCode::Escape) | (Scene::Title, KeyCode::Q) => event::quit(ctx),
+            (Scene::Title, KeyCode::E) => self.open_editor(ctx),
+            (Scene::Title, KeyCode::C) => {
+                self.control_row = 0;
+                self.scene = Scene::Controls;
+            }
+            (Scene::Title, KeyCode::Left) => self.difficulty = self.difficulty.easier(),
+            (Scene::Title, KeyCode::Right) => self.difficulty = self.difficulty.harder(),
+            (Scene::Title, KeyCode::Up) => {
+                self.level = (self.level + self.levels.len() - 1) % self.levels.len()
+            }
+            (Scene::Title, KeyCode::Down) => self.level = (self.level + 1) % self.levels.len(),
+            (Scene::Title, KeyCode::V) => self.players = self.players % MAX_PLAYERS + 1,
+            // Once the game is over, Enter starts another one, or in a match, goes
+            // on to the next round. Escape always leaves, whatever it's bound to.
+            (Scene::GameOver, KeyCode::Return) => self.next_round(ctx),
+            (Scene::GameOver, KeyCode::Escape) => self.leave_game(ctx),
+            // The editor has keys of its own.
+            (Scene::Editor, _) => self.editor_key(ctx, keycode, keymod),
+            _ => (),
+        }
+    }
+
+    // Letting go of the speed boost key puts the game back to its usual speed.
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        if let Some((_, Action::SpeedBoost)) = self.controls.action(key_name(keycode)) {
+            self.boosting = false;
+        }
+    }
+
+    // A controller's buttons do the same as the keys bound to the same actions, for
+    // whichever player the controller belongs to. Start also starts a game from the
+    // title screen, and goes on to the next round after one's over.
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        let player = match self.controllers.player(id) {
+            Some(player) if self.name_entry.is_none() => player,
+            _ => return,
+        };
+        match (self.scene, button) {
+            (Scene::Title, Button::Start) => self.start_match(ctx),
+            (Scene::GameOver, Button::Start) => self.next_round(ctx),
+            _ => {
+                if let Some(action) = gamepad_action(button) {
+                    self.game_action(ctx, player, action);
+                }
+            }
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+        if gamepad_action(button) == Some(Action::SpeedBoost) {
+            self.boosting = false;
+        }
+    }
+
+    // The left stick steers too. We only turn when it's pushed a new way, so
+    // holding it still doesn't keep turning the snake.
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        let player = match self.controllers.player(id) {
+            Some(player) => player,
+            None => return,
+        };
+        let stick = match self.controllers.stick(player) {
+            Some(stick) => stick,
+            None => return,
+        };
+        let dir = match axis {
+            Axis::LeftStickX => stick.set_x(value),
+            Axis::LeftStickY => stick.set_y(value),
+            _ => None,
+        };
+        if let Some(dir) = dir {
+            self.game_action(ctx, player, Action::Turn(dir));
+        }
+    }
+
+    // In the editor, holding the left mouse button down paints with the current
+    // brush, and holding the right one down rubs tiles out. Everything painted
+    // until the button is let go is undone together.
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if self.scene != Scene::Editor {
+            return;
+        }
+        let tile = match button {
+            MouseButton::Left => self.brush,
+            MouseButton::Right => MapTile::Empty,
+            _ => return,
+        };
+        self.editor.begin_stroke(tile);
+        if let Some(pos) = mouse_cell(ctx, x, y, self.config.cell_size) {
+            self.editor.stroke_to(pos);
+        }
+        self.editor_highlight = None;
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        _button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) {
+        self.editor.end_stroke();
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        if self.scene == Scene::Editor && self.editor.is_painting() {
+            if let Some(pos) = mouse_cell(ctx, x, y, self.config.cell_size) {
+                self.editor.stroke_to(pos);
+            }
+        }
+    }
+
+    // text_input_event gets fired with every character the player types, which is
+    // what we use to fill in their name for the high score table.
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if let Some(name) = &mut self.name_entry {
+            if !character.is_control() && name.chars().count() < MAX_NAME_LENGTH {
+                name.push(character);
             }
         }
     }
 }
 
 fn main() -> GameResult {
+    // Before doing anything else we read the options we were started with.
+    let options = Options::from_args()?;
+    let window_size = options.config.window_size();
+
     // Here we use a ContextBuilder to setup metadata about our game. First the title and author
-    let (ctx, event_loop) = ggez::ContextBuilder::new("snake", "Gray Olson")
+    let (mut ctx, event_loop) = ggez::ContextBuilder::new("snake", "Gray Olson")
         // Next we set up the window. This title will be displayed in the title bar of the window.
         .window_setup(ggez::conf::WindowSetup::default().title("Snake!"))
-        // Now we get to set the size of the window, which we use our SCREEN_SIZE constant from earlier to help with
+        // Then we tell ggez where to find our resources, like the tileset. When we're
+        // run with `cargo run`, that's the `resources` directory next to `Cargo.toml`.
+        .add_resource_path(
+            std::env::var("CARGO_MANIFEST_DIR")
+                .map(|dir| std::path::PathBuf::from(dir).join("resources"))
+                .unwrap_or_else(|_| std::path::PathBuf::from("resources")),
+        )
+        // Now we get to set the size of the window, which we take from our config
         .window_mode(
             ggez::conf::WindowMode::default()
-                .dimensions(SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32),
+                .dimensions(window_size.0 as f32, window_size.1 as f32),
         )
         // And finally we attempt to build the context and create the window. If it fails, we panic with the message
         // "Failed to build ggez context"
@@ -470,7 +1722,7 @@ fn main() -> GameResult {
         .expect("Failed to build ggez context");
 
     // Next we create a new instance of our GameState struct, which implements EventHandler
-    let state = GameState::new()?;
+    let state = GameState::new(&mut ctx, &options)?;
     // And finally we actually run our game, passing in our context, event_loop and state.
     event::run(ctx, event_loop, state)
 }
@@ -0,0 +1,197 @@
+use rand::Rng;
+
+use crate::grid::GridPosition;
+
+// An `OccupancyGrid` keeps track of which cells of the board are taken. It answers
+// "is this cell taken?" with a single bit lookup, and it also keeps a list of every
+// free cell so that we can pick one at random without scanning the whole board.
+// All of its operations take constant time, however large the board is.
+#[derive(Clone, Debug)]
+pub struct OccupancyGrid {
+    width: i16,
+    height: i16,
+    // One bit per cell, set when the cell is occupied.
+    bits: Vec<u64>,
+    // The index of every free cell, in no particular order.
+    free: Vec<u32>,
+    // For every cell, where its index sits in `free`. This is only meaningful
+    // while the cell is free; it lets us remove a cell from `free` by swapping
+    // it with the last entry instead of searching for it.
+    free_slot: Vec<u32>,
+}
+
+impl OccupancyGrid {
+    // We start out with a grid where every cell is free.
+    pub fn new(width: i16, height: i16) -> Self {
+        let cells = width as usize * height as usize;
+        OccupancyGrid {
+            width,
+            height,
+            bits: vec![0; cells.div_ceil(64)],
+            free: (0..cells as u32).collect(),
+            free_slot: (0..cells as u32).collect(),
+        }
+    }
+
+    pub fn width(&self) -> i16 {
+        self.width
+    }
+
+    pub fn height(&self) -> i16 {
+        self.height
+    }
+
+    // How many cells are still free.
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_occupied(&self, pos: GridPosition) -> bool {
+        let index = self.index(pos);
+        self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    // Marks a cell as taken. Occupying a cell that is already taken does nothing.
+    pub fn occupy(&mut self, pos: GridPosition) {
+        if self.is_occupied(pos) {
+            return;
+        }
+        let index = self.index(pos);
+        self.bits[index / 64] |= 1 << (index % 64);
+        // We take the cell out of the free list by moving the last free cell
+        // into its slot.
+        let slot = self.free_slot[index] as usize;
+        let last = self.free.pop().expect("a free cell is in the free list");
+        if last as usize != index {
+            self.free[slot] = last;
+            self.free_slot[last as usize] = slot as u32;
+        }
+    }
+
+    // Marks a cell as free again. Vacating a cell that is already free does nothing.
+    pub fn vacate(&mut self, pos: GridPosition) {
+        if !self.is_occupied(pos) {
+            return;
+        }
+        let index = self.index(pos);
+        self.bits[index / 64] &= !(1 << (index % 64));
+        self.free_slot[index] = self.free.len() as u32;
+        self.free.push(index as u32);
+    }
+
+    // Picks a free cell uniformly at random, or returns `None` if the whole
+    // grid is taken.
+    pub fn random_free<R: Rng>(&self, rng: &mut R) -> Option<GridPosition> {
+        if self.free.is_empty() {
+            return None;
+        }
+        let index = self.free[rng.gen_range(0..self.free.len())] as usize;
+        Some(self.position(index))
+    }
+
+    // Every free cell, in no particular order.
+    pub fn free_cells(&self) -> impl Iterator<Item = GridPosition> + '_ {
+        self.free
+            .iter()
+            .map(move |&index| self.position(index as usize))
+    }
+
+    // Cells are stored row by row.
+    fn index(&self, pos: GridPosition) -> usize {
+        pos.y as usize * self.width as usize + pos.x as usize
+    }
+
+    fn position(&self, index: usize) -> GridPosition {
+        GridPosition::new(
+            (index % self.width as usize) as i16,
+            (index / self.width as usize) as i16,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64;
+
+    // Checks that the free list holds exactly the cells that aren't occupied, each
+    // once, and that every free cell knows where it is in the list.
+    fn assert_consistent(grid: &OccupancyGrid) {
+        let cells = grid.width() as usize * grid.height() as usize;
+        let mut free: Vec<GridPosition> = grid.free_cells().collect();
+        assert_eq!(free.len(), grid.free_count());
+        for (slot, &index) in grid.free.iter().enumerate() {
+            assert_eq!(grid.free_slot[index as usize] as usize, slot);
+        }
+        free.sort_by_key(|pos| (pos.y, pos.x));
+        let expected: Vec<GridPosition> = (0..cells)
+            .map(|index| grid.position(index))
+            .filter(|&pos| !grid.is_occupied(pos))
+            .collect();
+        assert_eq!(free, expected);
+    }
+
+    #[test]
+    fn occupies_and_vacates_cells() {
+        let mut grid = OccupancyGrid::new(10, 8);
+        let pos = GridPosition::new(3, 7);
+        assert!(!grid.is_occupied(pos));
+        grid.occupy(pos);
+        assert!(grid.is_occupied(pos));
+        assert!(!grid.is_occupied(GridPosition::new(7, 3)));
+        assert_eq!(grid.free_count(), 79);
+        // Doing either twice changes nothing.
+        grid.occupy(pos);
+        assert_eq!(grid.free_count(), 79);
+        grid.vacate(pos);
+        grid.vacate(pos);
+        assert!(!grid.is_occupied(pos));
+        assert_eq!(grid.free_count(), 80);
+    }
+
+    #[test]
+    fn keeps_the_free_list_in_step() {
+        let mut grid = OccupancyGrid::new(9, 9);
+        for i in 0..81 {
+            if i % 3 != 1 {
+                grid.occupy(grid.position(i));
+            }
+        }
+        assert_consistent(&grid);
+        // Freeing cells in a different order from the one they were taken in
+        // shuffles the list about.
+        for i in (0..81).rev().step_by(4) {
+            grid.vacate(grid.position(i));
+        }
+        assert_consistent(&grid);
+        for i in (0..81).step_by(5) {
+            grid.occupy(grid.position(i));
+        }
+        assert_consistent(&grid);
+    }
+
+    #[test]
+    fn picks_only_free_cells_at_random() {
+        let mut grid = OccupancyGrid::new(4, 4);
+        let mut rng = Pcg64::seed_from_u64(3);
+        for i in 0..16 {
+            if i != 5 && i != 10 {
+                grid.occupy(grid.position(i));
+            }
+        }
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            let pos = grid.random_free(&mut rng).unwrap();
+            assert!(!grid.is_occupied(pos));
+            if !seen.contains(&pos) {
+                seen.push(pos);
+            }
+        }
+        // Both free cells turn up sooner or later.
+        assert_eq!(seen.len(), 2);
+        grid.occupy(GridPosition::new(1, 1));
+        grid.occupy(GridPosition::new(2, 2));
+        assert_eq!(grid.random_free(&mut rng), None);
+    }
+}
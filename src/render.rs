@@ -0,0 +1,587 @@
+use crate::board::Board;
+use crate::food::Food;
+use crate::grid::{Direction, GridPosition};
+use crate::levelfile::{LevelMap, MapTile};
+use crate::simulation::Simulation;
+use crate::snake::Snake;
+use crate::theme::{Color, SnakeColors, Theme};
+
+// Drawing the board boils down to drawing a pile of colored rectangles. This module
+// works out what those rectangles are, without knowing anything about how they end
+// up on the screen, so that a frontend can hand the whole lot to the GPU in one go
+// and anyone else can look at exactly what would have been drawn.
+
+// A rectangle in pixels, with `x` and `y` at its top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Rect { x, y, w, h }
+    }
+
+    // The rectangle covered by a single cell of the board.
+    pub fn cell(pos: GridPosition, cell_size: f32) -> Self {
+        Rect::new(
+            pos.x as f32 * cell_size,
+            pos.y as f32 * cell_size,
+            cell_size,
+            cell_size,
+        )
+    }
+
+    // To draw something partway between two cells, we take the rectangle of a
+    // cell and slide it `amount` of a cell in the direction `dir`.
+    pub fn shifted_cell(pos: GridPosition, dir: Direction, amount: f32, cell_size: f32) -> Self {
+        let (dx, dy) = dir.delta();
+        let cell = Rect::cell(pos, cell_size);
+        Rect::new(
+            cell.x + dx as f32 * amount * cell_size,
+            cell.y + dy as f32 * amount * cell_size,
+            cell.w,
+            cell.h,
+        )
+    }
+}
+
+// A filled rectangle of a single color, which is all we ever draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quad {
+    pub rect: Rect,
+    pub color: Color,
+}
+
+impl Quad {
+    pub fn new(rect: Rect, color: Color) -> Self {
+        Quad { rect, color }
+    }
+}
+
+// Clears `quads` and fills it with everything that makes up the board: the grid,
+// the walls, the snake and the food, in the order they should be drawn, in the
+// colors of `theme`. We take the `Vec` to fill rather than returning a new one so
+// that a frontend can keep reusing the same allocation from frame to frame. `alpha`
+// says how far we are through to the next update, which is used to draw the snake
+// moving smoothly.
+pub fn board_quads(
+    sim: &Simulation,
+    theme: &Theme,
+    cell_size: f32,
+    alpha: f32,
+    quads: &mut Vec<Quad>,
+) {
+    quads.clear();
+    terrain_quads(sim.board(), theme, cell_size, quads);
+    for (player, snake) in shown_snakes(sim) {
+        snake_quads(snake, &theme.snake(player), cell_size, alpha, quads);
+    }
+    for food in sim.food().iter() {
+        food_quads(food, theme, cell_size, quads);
+    }
+}
+
+// The snakes worth drawing, with the player each one belongs to. A snake that's
+// out of a game between several is gone from the board, but on our own we keep
+// drawing the snake after it crashes so the player can see what happened.
+fn shown_snakes(sim: &Simulation) -> impl Iterator<Item = (usize, &Snake)> {
+    let solo = sim.players() == 1;
+    sim.snakes()
+        .iter()
+        .enumerate()
+        .filter(move |&(player, _)| solo || sim.is_alive(player))
+}
+
+// Everything about the board that isn't the snake or its food: the grid lines, the
+// walls around the edge, if there are any, the obstacles and the portals.
+pub fn terrain_quads(board: &Board, theme: &Theme, cell_size: f32, quads: &mut Vec<Quad>) {
+    grid_quads(board, theme, cell_size, quads);
+    if board.has_walls() {
+        wall_quads(board, theme, cell_size, quads);
+    }
+    obstacle_quads(board, theme, cell_size, quads);
+    portal_quads(board, theme, cell_size, quads);
+}
+
+// Every obstacle fills its cell in the same color as the walls.
+pub fn obstacle_quads(board: &Board, theme: &Theme, cell_size: f32, quads: &mut Vec<Quad>) {
+    quads.extend(
+        board
+            .obstacles()
+            .map(|pos| Quad::new(Rect::cell(pos, cell_size), theme.walls)),
+    );
+}
+
+// Both ends of every portal are drawn as a square inset a little from the edges of
+// its cell, so they don't look like obstacles.
+pub fn portal_quads(board: &Board, theme: &Theme, cell_size: f32, quads: &mut Vec<Quad>) {
+    quads.extend(board.portals().flat_map(|(a, b)| {
+        [a, b].map(|pos| Quad::new(portal_rect(pos, cell_size), theme.portals))
+    }));
+}
+
+fn portal_rect(pos: GridPosition, cell_size: f32) -> Rect {
+    let inset = cell_size / 6.0;
+    let cell = Rect::cell(pos, cell_size);
+    Rect::new(
+        cell.x + inset,
+        cell.y + inset,
+        cell.w - inset * 2.0,
+        cell.h - inset * 2.0,
+    )
+}
+
+// Clears `quads` and fills it with a level map as the level editor shows it. Walls,
+// portals and food look the way they do in a game, and the start is drawn as the
+// snake's head with the segment behind it, so it's clear which way it's facing.
+// If `highlight` is given, that cell is marked, which the editor uses to point out
+// whatever is stopping the level from being played.
+pub fn editor_quads(
+    map: &LevelMap,
+    theme: &Theme,
+    cell_size: f32,
+    highlight: Option<GridPosition>,
+    quads: &mut Vec<Quad>,
+) {
+    quads.clear();
+    let board = Board::new(map.width() as i16, map.height() as i16).with_edges(map.edges);
+    grid_quads(&board, theme, cell_size, quads);
+    if board.has_walls() {
+        wall_quads(&board, theme, cell_size, quads);
+    }
+    for (pos, tile) in map.tiles() {
+        let cell = Rect::cell(pos, cell_size);
+        match tile {
+            MapTile::Empty => (),
+            MapTile::Wall => quads.push(Quad::new(cell, theme.walls)),
+            MapTile::Portal => quads.push(Quad::new(portal_rect(pos, cell_size), theme.portals)),
+            MapTile::Food => quads.push(Quad::new(cell, theme.food.normal)),
+            MapTile::Start => {
+                let tail = GridPosition::new_from_move(pos, map.direction.inverse(), &board);
+                if map.get(tail) == Some(MapTile::Empty) {
+                    quads.push(Quad::new(Rect::cell(tail, cell_size), theme.body_start));
+                }
+                quads.push(Quad::new(cell, theme.head));
+            }
+        }
+    }
+    if let Some(pos) = highlight {
+        let mut color = theme.hud;
+        color[3] *= 0.5;
+        quads.push(Quad::new(Rect::cell(pos, cell_size), color));
+    }
+}
+
+// If the theme has grid lines, we draw a thin line between every row and column
+// of cells.
+pub fn grid_quads(board: &Board, theme: &Theme, cell_size: f32, quads: &mut Vec<Quad>) {
+    if theme.grid_lines[3] <= 0.0 {
+        return;
+    }
+    let width = board.width as f32 * cell_size;
+    let height = board.height as f32 * cell_size;
+    let columns = (1..board.width).map(|x| Rect::new(x as f32 * cell_size, 0.0, 1.0, height));
+    let rows = (1..board.height).map(|y| Rect::new(0.0, y as f32 * cell_size, width, 1.0));
+    quads.extend(
+        columns
+            .chain(rows)
+            .map(|rect| Quad::new(rect, theme.grid_lines)),
+    );
+}
+
+// When the edges of the board are walls, we draw a border around it so the
+// player knows not to run into them. The border is made of four strips, one
+// along each edge, all inside the board.
+pub fn wall_quads(board: &Board, theme: &Theme, cell_size: f32, quads: &mut Vec<Quad>) {
+    let thickness = (cell_size / 8.0).max(1.0);
+    let width = board.width as f32 * cell_size;
+    let height = board.height as f32 * cell_size;
+    let strips = [
+        Rect::new(0.0, 0.0, width, thickness),
+        Rect::new(0.0, height - thickness, width, thickness),
+        Rect::new(0.0, 0.0, thickness, height),
+        Rect::new(width - thickness, 0.0, thickness, height),
+    ];
+    quads.extend(strips.iter().map(|&rect| Quad::new(rect, theme.walls)));
+}
+
+// Between updates, `alpha` says how far we are through to the next one. Rather than
+// jump the head and tail a whole cell every update, we use it to slide them there
+// smoothly: the head slides out of the cell it just left into its new one, and the
+// tail slides out of the cell it gave up. We slide along the direction they moved
+// rather than from one cell to the other, so when the snake wraps around the edge
+// of the board, the head slides in from just off the screen instead of streaking
+// all the way across it.
+pub fn snake_quads(
+    snake: &Snake,
+    colors: &SnakeColors,
+    cell_size: f32,
+    alpha: f32,
+    quads: &mut Vec<Quad>,
+) {
+    // If the tail gave up a cell in the last update, we draw the part of it that's
+    // still on its way out.
+    if let Some((pos, dir)) = snake.vacated_tail() {
+        quads.push(Quad::new(
+            Rect::shifted_cell(pos, dir, alpha, cell_size),
+            colors.body_end,
+        ));
+    }
+    // Then every body segment fills its cell, fading along the body from one color
+    // to the other.
+    let length = snake.length() - 1;
+    quads.extend(
+        snake.body().enumerate().map(|(i, seg)| {
+            Quad::new(Rect::cell(seg.pos, cell_size), colors.body_color(i, length))
+        }),
+    );
+    // And the head goes on top. If it moved in the last update, we draw it partway
+    // back towards where it came from.
+    let head = snake.head().pos;
+    let head_rect = match snake.last_move() {
+        Some(dir) => Rect::shifted_cell(head, dir, alpha - 1.0, cell_size),
+        None => Rect::cell(head, cell_size),
+    };
+    quads.push(Quad::new(head_rect, colors.head));
+}
+
+pub fn food_quads(food: &Food, theme: &Theme, cell_size: f32, quads: &mut Vec<Quad>) {
+    quads.push(Quad::new(
+        Rect::cell(food.pos, cell_size),
+        theme.food.color(food.kind),
+    ));
+}
+
+const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+
+// Instead of flat rectangles, the snake and its food can also be drawn from a
+// tileset: a single image with every tile laid out side by side in this order.
+// Each tile is drawn the way it looks when the snake is heading up the screen.
+// The head has its neck at the bottom, the tail joins the rest of the snake at
+// the top, the straight body piece runs from top to bottom, and the corner joins
+// the bottom edge to the right edge. Every other way round is the same tile
+// turned by some number of quarter turns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Head,
+    Body,
+    Corner,
+    Tail,
+    Food,
+}
+
+impl Tile {
+    pub const ALL: [Tile; 5] = [Tile::Head, Tile::Body, Tile::Corner, Tile::Tail, Tile::Food];
+
+    // Where the tile sits in the tileset, counting from the left.
+    pub fn index(&self) -> usize {
+        match *self {
+            Tile::Head => 0,
+            Tile::Body => 1,
+            Tile::Corner => 2,
+            Tile::Tail => 3,
+            Tile::Food => 4,
+        }
+    }
+
+    // The edges of the tile that join on to the rest of the snake, before it's turned.
+    fn edges(&self) -> &'static [Direction] {
+        match *self {
+            Tile::Head => &[Direction::Down],
+            Tile::Body => &[Direction::Up, Direction::Down],
+            Tile::Corner => &[Direction::Down, Direction::Right],
+            Tile::Tail => &[Direction::Up],
+            Tile::Food => &[],
+        }
+    }
+
+    // How many quarter turns clockwise the tile needs so that it joins on along
+    // exactly the given edges.
+    fn quarter_turns(&self, edges: &[Direction]) -> u8 {
+        (0..4)
+            .find(|&turns| {
+                self.edges().iter().all(|&edge| {
+                    let turned = (0..turns).fold(edge, |dir, _| dir.clockwise());
+                    edges.contains(&turned)
+                })
+            })
+            .unwrap_or(0)
+    }
+}
+
+// A tile drawn into a rectangle, turned clockwise by some number of quarter turns,
+// and tinted with a color. A white tint leaves the tile as it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub tile: Tile,
+    pub rect: Rect,
+    pub quarter_turns: u8,
+    pub tint: Color,
+}
+
+impl Sprite {
+    // Works out which way to turn `tile` so it joins on along `edges`.
+    pub fn new(tile: Tile, rect: Rect, edges: &[Direction]) -> Self {
+        Sprite {
+            tile,
+            rect,
+            quarter_turns: tile.quarter_turns(edges),
+            tint: WHITE,
+        }
+    }
+
+    pub fn with_tint(self, tint: Color) -> Self {
+        Sprite { tint, ..self }
+    }
+
+    // How far the tile is turned clockwise, in radians.
+    pub fn rotation(&self) -> f32 {
+        self.quarter_turns as f32 * std::f32::consts::FRAC_PI_2
+    }
+}
+
+// Clears `sprites` and fills it with the tiles for the snakes and the food. The
+// walls and obstacles are still drawn with `terrain_quads`. There's only one food tile, so it's
+// tinted with the theme's color for each kind of food. There's only one set of snake
+// tiles too, so every snake but the first is tinted with its head color to tell
+// them apart.
+pub fn board_sprites(
+    sim: &Simulation,
+    theme: &Theme,
+    cell_size: f32,
+    alpha: f32,
+    sprites: &mut Vec<Sprite>,
+) {
+    sprites.clear();
+    for (player, snake) in shown_snakes(sim) {
+        let tint = match player {
+            0 => WHITE,
+            _ => theme.snake(player).head,
+        };
+        snake_sprites(snake, sim.board(), tint, cell_size, alpha, sprites);
+    }
+    for food in sim.food().iter() {
+        sprites.push(
+            Sprite::new(Tile::Food, Rect::cell(food.pos, cell_size), &[])
+                .with_tint(theme.food.color(food.kind)),
+        );
+    }
+}
+
+// Every part of the snake picks its tile by looking at which of its neighbors
+// it joins on to. Like `snake_quads`, the head and tail slide between cells by
+// `alpha` of a cell so the snake moves smoothly.
+pub fn snake_sprites(
+    snake: &Snake,
+    board: &Board,
+    tint: Color,
+    cell_size: f32,
+    alpha: f32,
+    sprites: &mut Vec<Sprite>,
+) {
+    // We line the whole snake up from head to tail, including the cell the tail is
+    // sliding out of, if there is one.
+    let mut cells: Vec<GridPosition> = std::iter::once(snake.head().pos)
+        .chain(snake.body().map(|seg| seg.pos))
+        .collect();
+    let vacated = snake.vacated_tail();
+    if let Some((pos, _)) = vacated {
+        cells.push(pos);
+    }
+    // Which way to go from one cell to reach its neighbor. On a wrapping board
+    // the neighbor may be on the far side of the board, which `direction_between`
+    // takes care of. Where the snake went through a portal, the cell it came out
+    // of can be reached from the one before it, but not the other way round, so
+    // looking back we take the way it went in and turn it around. That way the
+    // cell at the far end of the portal joins on along the edge the snake came in
+    // by. If neither way works, the snake has just run into itself, and then we
+    // just use the way it's going.
+    let towards = |from: GridPosition, to: GridPosition| {
+        board
+            .direction_between(from, to)
+            .or_else(|| board.direction_between(to, from).map(|dir| dir.inverse()))
+            .unwrap_or_else(|| snake.last_update_dir().inverse())
+    };
+    let last = cells.len() - 1;
+    for (i, &pos) in cells.iter().enumerate() {
+        let sprite = if i == 0 {
+            let rect = match snake.last_move() {
+                Some(dir) => Rect::shifted_cell(pos, dir, alpha - 1.0, cell_size),
+                None => Rect::cell(pos, cell_size),
+            };
+            Sprite::new(Tile::Head, rect, &[towards(pos, cells[1])])
+        } else if i == last {
+            let rect = match vacated {
+                Some((_, dir)) => Rect::shifted_cell(pos, dir, alpha, cell_size),
+                None => Rect::cell(pos, cell_size),
+            };
+            Sprite::new(Tile::Tail, rect, &[towards(pos, cells[i - 1])])
+        } else {
+            let edges = [towards(pos, cells[i - 1]), towards(pos, cells[i + 1])];
+            let tile = if edges[0] == edges[1].inverse() {
+                Tile::Body
+            } else {
+                Tile::Corner
+            };
+            Sprite::new(tile, Rect::cell(pos, cell_size), &edges)
+        };
+        sprites.push(sprite.with_tint(tint));
+    }
+    // We draw from the tail up so the head ends up on top.
+    let start = sprites.len() - cells.len();
+    sprites[start..].reverse();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{BoardView, EdgePolicy};
+    use crate::food::FoodField;
+    use crate::level::Level;
+    use crate::theme::BuiltinTheme;
+
+    // A snake that has eaten its way right along row 5 and then turned down, so it
+    // runs from its head at (4, 6) round the corner at (4, 5) back to (2, 5), with
+    // its tail just leaving (1, 5).
+    fn cornering_snake(board: &Board) -> Snake {
+        let mut food = FoodField::new();
+        food.insert(Food::new(GridPosition::new(3, 5)));
+        food.insert(Food::new(GridPosition::new(4, 5)));
+        let mut snake = Snake::new(GridPosition::new(2, 5), Direction::Right, board);
+        snake.update(&BoardView::new(board, &food));
+        snake.update(&BoardView::new(board, &food));
+        snake.turn(Direction::Down);
+        snake.update(&BoardView::new(board, &FoodField::new()));
+        snake
+    }
+
+    #[test]
+    fn joins_tiles_up_through_a_portal() {
+        let mut board = Board::new(10, 10);
+        board.add_portal(GridPosition::new(5, 5), GridPosition::new(2, 8));
+        let mut food = FoodField::new();
+        food.insert(Food::new(GridPosition::new(4, 5)));
+        // The snake eats its way up to the portal, goes in at (5, 5), comes out at
+        // (2, 8) and turns down.
+        let mut snake = Snake::new(GridPosition::new(3, 5), Direction::Right, &board);
+        snake.update(&BoardView::new(&board, &food));
+        snake.update(&BoardView::new(&board, &FoodField::new()));
+        assert_eq!(snake.head().pos, GridPosition::new(2, 8));
+        snake.turn(Direction::Down);
+        snake.update(&BoardView::new(&board, &FoodField::new()));
+        let mut sprites = Vec::new();
+        snake_sprites(&snake, &board, WHITE, 10.0, 1.0, &mut sprites);
+        let tiles: Vec<(Tile, u8)> = sprites
+            .iter()
+            .map(|sprite| (sprite.tile, sprite.quarter_turns))
+            .collect();
+        assert_eq!(
+            tiles,
+            vec![
+                (Tile::Tail, 1),
+                (Tile::Body, 1),
+                // The segment that came out of the portal joins on to the left,
+                // where the snake came in, and below, where it's going.
+                (Tile::Corner, 1),
+                (Tile::Head, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn draws_every_part_of_a_small_board() {
+        let level = Level {
+            start: GridPosition::new(1, 1),
+            food: vec![GridPosition::new(3, 2)],
+            ..Level::open(Board::new(4, 3).with_edges(EdgePolicy::Solid))
+        };
+        let sim = Simulation::from_level(level, 1);
+        let theme = Theme {
+            grid_lines: [0.0, 0.0, 0.0, 1.0],
+            ..BuiltinTheme::Classic.theme()
+        };
+        let mut quads = Vec::new();
+        board_quads(&sim, &theme, 10.0, 0.0, &mut quads);
+        let rects: Vec<Rect> = quads.iter().map(|quad| quad.rect).collect();
+        assert_eq!(
+            rects,
+            vec![
+                // Three lines between the columns and two between the rows...
+                Rect::new(10.0, 0.0, 1.0, 30.0),
+                Rect::new(20.0, 0.0, 1.0, 30.0),
+                Rect::new(30.0, 0.0, 1.0, 30.0),
+                Rect::new(0.0, 10.0, 40.0, 1.0),
+                Rect::new(0.0, 20.0, 40.0, 1.0),
+                // ...a strip along each wall...
+                Rect::new(0.0, 0.0, 40.0, 1.25),
+                Rect::new(0.0, 28.75, 40.0, 1.25),
+                Rect::new(0.0, 0.0, 1.25, 30.0),
+                Rect::new(38.75, 0.0, 1.25, 30.0),
+                // ...the snake's body and then its head...
+                Rect::new(0.0, 10.0, 10.0, 10.0),
+                Rect::new(10.0, 10.0, 10.0, 10.0),
+                // ...and the food.
+                Rect::new(30.0, 20.0, 10.0, 10.0),
+            ]
+        );
+        assert_eq!(quads[10].color, theme.head);
+        assert_eq!(quads[11].color, theme.food.normal);
+    }
+
+    #[test]
+    fn leaves_out_the_grid_and_walls_when_there_are_none() {
+        let sim = Simulation::from_level(Level::open(Board::new(4, 3)), 1);
+        let mut quads = Vec::new();
+        board_quads(&sim, &BuiltinTheme::Classic.theme(), 10.0, 0.0, &mut quads);
+        // Just the snake's two cells and one piece of food.
+        assert_eq!(quads.len(), 3);
+    }
+
+    #[test]
+    fn slides_the_head_and_tail_of_a_snake_round_a_corner() {
+        let board = Board::new(10, 10);
+        let snake = cornering_snake(&board);
+        let colors = SnakeColors::default();
+        let mut quads = Vec::new();
+        snake_quads(&snake, &colors, 10.0, 0.25, &mut quads);
+        assert_eq!(
+            quads,
+            vec![
+                // The tail is a quarter of the way out of the cell it left.
+                Quad::new(Rect::new(12.5, 50.0, 10.0, 10.0), colors.body_end),
+                // The body fills its cells from the corner back.
+                Quad::new(Rect::new(40.0, 50.0, 10.0, 10.0), colors.body_color(0, 3)),
+                Quad::new(Rect::new(30.0, 50.0, 10.0, 10.0), colors.body_color(1, 3)),
+                Quad::new(Rect::new(20.0, 50.0, 10.0, 10.0), colors.body_color(2, 3)),
+                // The head is a quarter of the way down into its new cell.
+                Quad::new(Rect::new(40.0, 52.5, 10.0, 10.0), colors.head),
+            ]
+        );
+    }
+
+    #[test]
+    fn picks_and_turns_tiles_for_a_snake_round_a_corner() {
+        let board = Board::new(10, 10);
+        let snake = cornering_snake(&board);
+        let mut sprites = Vec::new();
+        snake_sprites(&snake, &board, WHITE, 10.0, 1.0, &mut sprites);
+        let tiles: Vec<(Tile, u8, Rect)> = sprites
+            .iter()
+            .map(|sprite| (sprite.tile, sprite.quarter_turns, sprite.rect))
+            .collect();
+        assert_eq!(
+            tiles,
+            vec![
+                (Tile::Tail, 1, Rect::new(20.0, 50.0, 10.0, 10.0)),
+                (Tile::Body, 1, Rect::new(20.0, 50.0, 10.0, 10.0)),
+                (Tile::Body, 1, Rect::new(30.0, 50.0, 10.0, 10.0)),
+                (Tile::Corner, 1, Rect::new(40.0, 50.0, 10.0, 10.0)),
+                (Tile::Head, 2, Rect::new(40.0, 60.0, 10.0, 10.0)),
+            ]
+        );
+    }
+}
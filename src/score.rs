@@ -0,0 +1,73 @@
+use crate::food::FoodKind;
+
+// These are the rules that decide how many points the player gets. They live
+// here in the simulation rather than in whatever draws the score, so every
+// frontend agrees on what a game was worth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScoreRules {
+    // How many points a piece of food is worth before any multiplier.
+    pub points_per_food: u32,
+    // Every time the snake eats this many pieces of food, the multiplier goes up by one.
+    pub foods_per_multiplier: u32,
+    // The multiplier never goes above this.
+    pub max_multiplier: u32,
+}
+
+impl Default for ScoreRules {
+    fn default() -> Self {
+        ScoreRules {
+            points_per_food: 10,
+            foods_per_multiplier: 5,
+            max_multiplier: 5,
+        }
+    }
+}
+
+// The `Score` keeps a running tally of a single game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Score {
+    rules: ScoreRules,
+    points: u32,
+    foods_eaten: u32,
+}
+
+impl Score {
+    pub fn new(rules: ScoreRules) -> Self {
+        Score {
+            rules,
+            points: 0,
+            foods_eaten: 0,
+        }
+    }
+
+    pub fn rules(&self) -> &ScoreRules {
+        &self.rules
+    }
+
+    pub fn points(&self) -> u32 {
+        self.points
+    }
+
+    pub fn foods_eaten(&self) -> u32 {
+        self.foods_eaten
+    }
+
+    // The multiplier starts at 1 and climbs as the snake keeps eating.
+    pub fn multiplier(&self) -> u32 {
+        let steps = self.foods_eaten / self.rules.foods_per_multiplier.max(1);
+        (1 + steps).min(self.rules.max_multiplier.max(1))
+    }
+
+    // Records a piece of food being eaten and returns how many points it was worth.
+    // Some kinds of food are worth more than others, and food worth nothing doesn't
+    // count as eaten at all.
+    pub fn add_food(&mut self, kind: FoodKind) -> u32 {
+        if kind.value() == 0 {
+            return 0;
+        }
+        let points = self.rules.points_per_food * kind.value() * self.multiplier();
+        self.points += points;
+        self.foods_eaten += 1;
+        points
+    }
+}
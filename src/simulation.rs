@@ -0,0 +1,626 @@
+use std::time::Duration;
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+use crate::board::{Board, BoardView};
+use crate::difficulty::Difficulty;
+use crate::food::{Food, FoodField, FoodKind};
+use crate::grid::{Direction, GridPosition};
+use crate::level::{Level, WinCondition};
+use crate::score::{Score, ScoreRules};
+use crate::snake::{Ate, Snake, Steer};
+
+// These are the things that can happen during a single step of the simulation.
+// A frontend can look at them to play sounds, flash the screen, and so on, but
+// it never has to know *how* they came about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    // This player's snake ate the piece of food of this kind that was sitting at
+    // this position. Players are counted from 0, in the order their snakes started.
+    AteFood(usize, GridPosition, FoodKind),
+    // A new piece of food of this kind was placed at this position.
+    FoodSpawned(GridPosition, FoodKind),
+    // The piece of food at this position ran out of time and disappeared.
+    FoodExpired(GridPosition),
+    // This player scored this many points.
+    Scored(usize, u32),
+    // The game reached this level, and sped up.
+    LevelUp(u32),
+    // This player's snake ran into something, and is out of the game.
+    Died(usize),
+    // The game is over, and this is how it ended.
+    GameOver(Outcome),
+}
+
+// A game of snake for one player can end in one of two ways...
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    // The snake filled the whole board, so there's nowhere left to put food, or
+    // did whatever else the level asked for.
+    Won,
+    // The snake ran into itself or into a wall.
+    Lost,
+    // ...and a game between several snakes in one of two more. Either this player
+    // won, by being the last snake left, by doing what the level asked for first,
+    // or by having the best score when the board filled up...
+    Winner(usize),
+    // ...or nobody did, because the last snakes left all died at once, or tied.
+    Draw,
+}
+
+// A step can produce more than one event (eating food spawns another piece,
+// for instance), so we hand them back as a list.
+pub type Events = Vec<Event>;
+
+// The `Simulation` holds everything needed to describe the state of a game of
+// snake and to advance it one tick at a time. It knows nothing about windows,
+// graphics or keyboards, which means it can be run as fast as we like on a machine
+// without a GPU.
+pub struct Simulation {
+    // First we need the board the game is played on
+    board: Board,
+    // A Snake for every player, and whether it's still in the game. Most games
+    // only have the one.
+    snakes: Vec<Snake>,
+    alive: Vec<bool>,
+    // The food lying on the board, and how many pieces we try to keep there
+    food: FoodField,
+    food_count: usize,
+    // Each player's score so far, and what it takes to win
+    scores: Vec<Score>,
+    win: WinCondition,
+    // How fast the game runs, and how quickly it speeds up
+    difficulty: Difficulty,
+    // How much faster or slower than usual the game is running because of
+    // something the snake ate, and for how many more ticks
+    speed_boost: f32,
+    boost_ticks: u32,
+    // How the game ended, or `None` if it's still going
+    outcome: Option<Outcome>,
+    // The seed this game was started with, so that it can be shown to the
+    // player and used to replay the exact same game later.
+    seed: u64,
+    // And the source of randomness we use for every random decision in the game.
+    // We use a PCG generator rather than `StdRng` because its output is
+    // guaranteed to stay the same across versions of `rand`, which means a seed
+    // always replays the same game bit-for-bit.
+    rng: Pcg64,
+}
+
+impl Simulation {
+    // Our new function will set up the initial state of our game on the given board,
+    // with nothing else on it. Two simulations created with the same board and `seed`
+    // and fed the same inputs will play out identically.
+    pub fn new(board: Board, seed: u64) -> Self {
+        Simulation::from_level(Level::open(board), seed)
+    }
+
+    // Sets up a game on a level, which decides the board, where the snake starts,
+    // any food that's there from the start and what it takes to win. The level's
+    // speed is left to `Level::difficulty`.
+    pub fn from_level(level: Level, seed: u64) -> Self {
+        Simulation::versus(level, 1, seed)
+    }
+
+    // Sets up a game on a level between `players` snakes, all after the same food.
+    // Where each of them starts is up to `Level::starts`, and if the level is too
+    // crowded to fit them all, fewer of them play.
+    pub fn versus(level: Level, players: usize, seed: u64) -> Self {
+        let rng = Pcg64::seed_from_u64(seed);
+        // First we put our snakes wherever the level says they start.
+        let starts = level.starts(players.max(1));
+        let board = level.board;
+        let snakes: Vec<Snake> = starts
+            .iter()
+            .map(|&(pos, dir)| Snake::new(pos, dir, &board))
+            .collect();
+        // Then we lay out the level's own food, wherever it isn't in a snake's way.
+        let mut food = FoodField::new();
+        for &pos in level.food.iter() {
+            if board.contains(pos) && !snakes.iter().any(|s| s.occupancy().is_occupied(pos)) {
+                food.insert(Food::new(pos));
+            }
+        }
+
+        let mut sim = Simulation {
+            board,
+            alive: vec![true; snakes.len()],
+            scores: vec![Score::new(ScoreRules::default()); snakes.len()],
+            snakes,
+            food,
+            food_count: 1,
+            win: level.win,
+            difficulty: Difficulty::default(),
+            speed_boost: 0.0,
+            boost_ticks: 0,
+            outcome: None,
+            seed,
+            rng,
+        };
+        // And if that isn't enough food, we choose a random empty place to put some more.
+        sim.spawn_food(&mut Events::new());
+        sim
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // Plays the game with a different speed curve than the default.
+    pub fn with_difficulty(self, difficulty: Difficulty) -> Self {
+        Simulation { difficulty, ..self }
+    }
+
+    // Keeps `count` pieces of food on the board at once instead of just one.
+    pub fn with_food_count(mut self, count: usize) -> Self {
+        self.food_count = count.max(1);
+        self.spawn_food(&mut Events::new());
+        self
+    }
+
+    pub fn food_count(&self) -> usize {
+        self.food_count
+    }
+
+    pub fn difficulty(&self) -> &Difficulty {
+        &self.difficulty
+    }
+
+    // The level the game is on, based on how much the snakes have eaten between them.
+    pub fn level(&self) -> u32 {
+        self.difficulty.level(self.foods_eaten())
+    }
+
+    fn foods_eaten(&self) -> u32 {
+        self.scores.iter().map(|score| score.foods_eaten()).sum()
+    }
+
+    // How many updates per second the game should run at right now. On top of
+    // the difficulty, food that speeds the game up or slows it down changes this
+    // for a while, but it never drops below one update a second.
+    pub fn speed(&self) -> f32 {
+        let speed = self.difficulty.speed(self.foods_eaten());
+        if self.boost_ticks > 0 {
+            (speed + self.speed_boost).max(1.0)
+        } else {
+            speed
+        }
+    }
+
+    // How long to wait between calls to `step` right now. Frontends use this
+    // instead of a fixed tick rate so that the game speeds up as the snake grows.
+    pub fn update_interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.speed())
+    }
+
+    // The first player's snake, which in a game for one is the only snake there is.
+    pub fn snake(&self) -> &Snake {
+        &self.snakes[0]
+    }
+
+    // Every player's snake, including any that are out of the game.
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes
+    }
+
+    pub fn players(&self) -> usize {
+        self.snakes.len()
+    }
+
+    pub fn is_alive(&self, player: usize) -> bool {
+        self.alive.get(player) == Some(&true)
+    }
+
+    pub fn food(&self) -> &FoodField {
+        &self.food
+    }
+
+    // The first player's score.
+    pub fn score(&self) -> &Score {
+        &self.scores[0]
+    }
+
+    pub fn scores(&self) -> &[Score] {
+        &self.scores
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Here we advance the game world by exactly one tick. `inputs` are the
+    // directions the player asked for since the last step, in the order they
+    // were pressed. We return everything that happened along the way. In a game
+    // between several snakes, this steers only the first one.
+    pub fn step(&mut self, inputs: &[Direction]) -> Events {
+        let steers: Vec<Steer> = inputs.iter().map(|&dir| Steer::Face(dir)).collect();
+        self.step_players(&[&steers])
+    }
+
+    // Advances a game between any number of snakes by one tick. `inputs` holds the
+    // way each player asked to steer, in player order, whether that's to face a
+    // direction or to turn from wherever their snake is heading.
+    pub fn step_players(&mut self, inputs: &[&[Steer]]) -> Events {
+        let mut events = Events::new();
+        // If the game is over, we'll just do nothing.
+        if self.is_over() {
+            return events;
+        }
+        // First we queue up whatever turns the players pressed.
+        for (snake, steers) in self.snakes.iter_mut().zip(inputs) {
+            for &steer in steers.iter() {
+                snake.steer(steer);
+            }
+        }
+        // Then we tell every snake still in the game to update itself, letting it see
+        // the board and the food on it. They all move at once, so none of them gets
+        // to go first.
+        let view = BoardView::new(&self.board, &self.food);
+        for (snake, &alive) in self.snakes.iter_mut().zip(self.alive.iter()) {
+            if alive {
+                snake.update(&view);
+            }
+        }
+        // Any change in speed from something a snake ate earlier wears off a tick at a time.
+        self.boost_ticks = self.boost_ticks.saturating_sub(1);
+
+        // Next we find out which snakes crashed. We work this out for every snake
+        // from where they all ended up before taking any of them away, so the result
+        // never depends on which player is which.
+        let crashed: Vec<usize> = (0..self.players())
+            .filter(|&player| self.alive[player] && self.crashed(player))
+            .collect();
+        for &player in crashed.iter() {
+            self.alive[player] = false;
+            events.push(Event::Died(player));
+        }
+
+        // Then every snake that's left and ate a piece of food scores for it, and
+        // whatever else that kind of food does happens.
+        let mut ate_any = false;
+        let mut winners = Vec::new();
+        for player in 0..self.players() {
+            let kind = match (self.alive[player], self.snakes[player].ate()) {
+                (true, Some(Ate::Food(kind))) => kind,
+                _ => continue,
+            };
+            ate_any = true;
+            let pos = self.snakes[player].head().pos;
+            self.food.remove(pos);
+            events.push(Event::AteFood(player, pos, kind));
+            let level = self.level();
+            let points = self.scores[player].add_food(kind);
+            if points > 0 {
+                events.push(Event::Scored(player, points));
+            }
+            if self.level() > level {
+                events.push(Event::LevelUp(self.level()));
+            }
+            if let Some((boost, ticks)) = kind.speed_effect() {
+                self.speed_boost = boost;
+                self.boost_ticks = ticks;
+            }
+            if self.has_won(player) {
+                winners.push(player);
+            }
+        }
+
+        // Now we see whether the game is over...
+        let survivors: Vec<usize> = (0..self.players()).filter(|&p| self.alive[p]).collect();
+        let outcome = match (self.players(), winners.as_slice(), survivors.as_slice()) {
+            // On our own, running into anything loses, and doing what the level
+            // asks for wins.
+            (1, _, []) => Some(Outcome::Lost),
+            (1, [_], _) => Some(Outcome::Won),
+            (1, _, _) => None,
+            // Against other snakes, doing what the level asks for first wins, unless
+            // someone else did it at the same time...
+            (_, [winner], _) => Some(Outcome::Winner(*winner)),
+            (_, [_, _, ..], _) => Some(Outcome::Draw),
+            // ...and otherwise the last snake left wins.
+            (_, [], []) => Some(Outcome::Draw),
+            (_, [], [survivor]) => Some(Outcome::Winner(*survivor)),
+            (_, [], _) => None,
+        };
+        if let Some(outcome) = outcome {
+            self.end(outcome, &mut events);
+            return events;
+        }
+        // ...and if it isn't, all the food that's left gets a tick older, whether or
+        // not some was eaten, and any whose time is up disappears. Then we place new
+        // food for any that was eaten or ran out.
+        let expired = self.food.tick();
+        events.extend(expired.iter().map(|food| Event::FoodExpired(food.pos)));
+        if ate_any || !expired.is_empty() {
+            self.spawn_food(&mut events);
+        }
+        events
+    }
+
+    // Whether a player's snake ran into something in the last update: itself, a
+    // wall, or any part of another snake that's still in the game, head included.
+    // Two snakes that run into each other head on both crash.
+    fn crashed(&self, player: usize) -> bool {
+        let snake = &self.snakes[player];
+        match snake.ate() {
+            Some(Ate::Itself) | Some(Ate::Wall) => true,
+            _ => {
+                let head = snake.head().pos;
+                (0..self.players()).any(|other| {
+                    other != player
+                        && self.alive[other]
+                        && self.snakes[other].occupancy().is_occupied(head)
+                })
+            }
+        }
+    }
+
+    // We top the board back up to `food_count` pieces of food, each of a random kind
+    // in a random free cell. If we can't place any at all, the snakes have filled the
+    // whole board. On our own, that's a win. Against other snakes, the best score
+    // left on the board wins.
+    fn spawn_food(&mut self, events: &mut Events) {
+        while self.food.len() < self.food_count {
+            let snakes: Vec<&Snake> = self
+                .snakes
+                .iter()
+                .zip(self.alive.iter())
+                .filter(|&(_, &alive)| alive)
+                .map(|(snake, _)| snake)
+                .collect();
+            match random_food(&mut self.rng, &self.board, &snakes, &self.food) {
+                Some(food) => {
+                    self.food.insert(food);
+                    events.push(Event::FoodSpawned(food.pos, food.kind));
+                }
+                None => break,
+            }
+        }
+        if self.food.is_empty() {
+            let outcome = if self.players() == 1 {
+                Outcome::Won
+            } else {
+                self.best_score()
+            };
+            self.end(outcome, events);
+        }
+    }
+
+    // The player with the best score of those still in the game, if there's one
+    // clear winner.
+    fn best_score(&self) -> Outcome {
+        let points = |player: usize| self.scores[player].points();
+        let survivors: Vec<usize> = (0..self.players()).filter(|&p| self.alive[p]).collect();
+        let best = survivors.iter().map(|&p| points(p)).max();
+        let leaders: Vec<usize> = survivors
+            .into_iter()
+            .filter(|&p| Some(points(p)) == best)
+            .collect();
+        match leaders.as_slice() {
+            [winner] => Outcome::Winner(*winner),
+            _ => Outcome::Draw,
+        }
+    }
+
+    // Whether a player has done what the level asks of them. Filling the board
+    // is checked separately, when there's nowhere left to put food.
+    fn has_won(&self, player: usize) -> bool {
+        let score = &self.scores[player];
+        match self.win {
+            WinCondition::Fill => false,
+            WinCondition::Score(points) => score.points() >= points,
+            WinCondition::Length(length) => self.snakes[player].length() >= length,
+            WinCondition::Food(count) => score.foods_eaten() >= count,
+        }
+    }
+
+    fn end(&mut self, outcome: Outcome, events: &mut Events) {
+        self.outcome = Some(outcome);
+        events.push(Event::GameOver(outcome));
+    }
+}
+
+// How many times we try picking a cell at random before we give up and look
+// through every free cell instead.
+const SPAWN_ATTEMPTS: usize = 16;
+
+// Here we pick a random cell that isn't covered by a snake, any food, an
+// obstacle or a portal, or return `None` if there isn't one. We only ever sample from
+// the cells the first snake leaves free, so however long it gets, food never ends up
+// hidden underneath it. Most of the time the first cell we pick is empty apart from
+// that, but when the board is nearly full, we'd rather not keep guessing, so after a
+// few tries we pick from the cells that are actually empty.
+fn random_free_cell<R: Rng>(
+    rng: &mut R,
+    board: &Board,
+    snakes: &[&Snake],
+    food: &FoodField,
+) -> Option<GridPosition> {
+    let (first, others) = snakes.split_first()?;
+    let occupancy = first.occupancy();
+    let is_empty = |pos: GridPosition| {
+        !food.contains(pos)
+            && !board.is_obstacle(pos)
+            && !board.is_portal(pos)
+            && !others
+                .iter()
+                .any(|snake| snake.occupancy().is_occupied(pos))
+    };
+    for _ in 0..SPAWN_ATTEMPTS {
+        let pos = occupancy.random_free(rng)?;
+        if is_empty(pos) {
+            return Some(pos);
+        }
+    }
+    let empty: Vec<GridPosition> = occupancy
+        .free_cells()
+        .filter(|&pos| is_empty(pos))
+        .collect();
+    if empty.is_empty() {
+        None
+    } else {
+        Some(empty[rng.gen_range(0..empty.len())])
+    }
+}
+
+// A new piece of food of a random kind, in a random empty cell.
+fn random_food<R: Rng>(
+    rng: &mut R,
+    board: &Board,
+    snakes: &[&Snake],
+    food: &FoodField,
+) -> Option<Food> {
+    let pos = random_free_cell(rng, board, snakes, food)?;
+    Some(Food::with_kind(pos, FoodKind::random(rng)))
+}
+
+// If nobody cares which game they get, we just pick a random seed on the
+// default board.
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::new(Board::default(), rand::random())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::EdgePolicy;
+
+    // An open level with the snake at (2, 2) heading right and food in `food`.
+    fn level(width: i16, height: i16, edges: EdgePolicy, food: &[(i16, i16)]) -> Level {
+        Level {
+            start: GridPosition::new(2, 2),
+            food: food.iter().map(|&pos| pos.into()).collect(),
+            ..Level::open(Board::new(width, height).with_edges(edges))
+        }
+    }
+
+    #[test]
+    fn same_seed_plays_the_same_game() {
+        let play = || {
+            let mut sim = Simulation::new(Board::new(20, 12), 42).with_food_count(3);
+            let mut trace = Vec::new();
+            for tick in 0..300 {
+                let dir = Direction::ALL[(tick / 7) % 4];
+                let events = sim.step(&[dir]);
+                let food: Vec<GridPosition> = sim.food().iter().map(|food| food.pos).collect();
+                trace.push((events, sim.snake().head().pos, sim.score().points(), food));
+            }
+            trace
+        };
+        assert_eq!(play(), play());
+    }
+
+    #[test]
+    fn eating_food_grows_the_snake_and_scores() {
+        let mut sim = Simulation::from_level(level(10, 6, EdgePolicy::Wrap, &[(3, 2)]), 1);
+        let events = sim.step(&[]);
+        assert!(events.contains(&Event::AteFood(
+            0,
+            GridPosition::new(3, 2),
+            FoodKind::Normal
+        )));
+        assert!(events.contains(&Event::Scored(0, 10)));
+        assert_eq!(sim.snake().length(), 3);
+        assert_eq!(sim.score().points(), 10);
+        assert_eq!(sim.score().foods_eaten(), 1);
+    }
+
+    #[test]
+    fn food_ages_on_the_tick_other_food_is_eaten() {
+        let mut sim = Simulation::from_level(level(10, 6, EdgePolicy::Wrap, &[(3, 2)]), 1);
+        let bonus = GridPosition::new(8, 4);
+        sim.food.insert(Food {
+            ticks_left: Some(2),
+            ..Food::with_kind(bonus, FoodKind::Bonus)
+        });
+        let events = sim.step(&[]);
+        assert!(events.contains(&Event::AteFood(
+            0,
+            GridPosition::new(3, 2),
+            FoodKind::Normal
+        )));
+        assert_eq!(
+            sim.food().get(bonus).and_then(|food| food.ticks_left),
+            Some(1)
+        );
+        let events = sim.step(&[]);
+        assert!(events.contains(&Event::FoodExpired(bonus)));
+        assert!(!sim.food().contains(bonus));
+    }
+
+    #[test]
+    fn food_never_turns_up_on_a_snake_or_an_obstacle() {
+        let walls = [(1, 1), (3, 1), (2, 3)].iter().map(|&pos| pos.into());
+        for seed in 0..50 {
+            let level = Level {
+                start: GridPosition::new(1, 2),
+                ..Level::open(Board::new(5, 4).with_obstacles(walls.clone()))
+            };
+            let mut sim = Simulation::from_level(level, seed).with_food_count(4);
+            for tick in 0..40 {
+                for food in sim.food().iter() {
+                    assert!(!sim.board().is_obstacle(food.pos), "seed {}", seed);
+                    assert!(!sim.snake().occupies(food.pos), "seed {}", seed);
+                }
+                if sim.is_over() {
+                    break;
+                }
+                sim.step(&[Direction::ALL[(tick / 3 + seed as usize) % 4]]);
+            }
+        }
+    }
+
+    #[test]
+    fn filling_the_board_wins() {
+        for seed in 0..10 {
+            // The snake takes up two of the three cells, so the only place for food
+            // is the last one, and eating it leaves nowhere for the next piece.
+            let mut sim = Simulation::new(Board::new(3, 1), seed);
+            assert_eq!(sim.food().len(), 1);
+            assert!(sim.food().contains(GridPosition::new(1, 0)));
+            let events = sim.step(&[]);
+            assert!(events.contains(&Event::GameOver(Outcome::Won)));
+            assert_eq!(sim.outcome(), Some(Outcome::Won));
+            assert!(sim.food().is_empty());
+        }
+    }
+
+    #[test]
+    fn running_into_a_wall_loses() {
+        let mut sim = Simulation::from_level(level(5, 5, EdgePolicy::Solid, &[]), 1);
+        let mut events = Events::new();
+        for _ in 0..3 {
+            events = sim.step(&[]);
+        }
+        assert!(sim.is_over());
+        assert_eq!(sim.outcome(), Some(Outcome::Lost));
+        assert!(events.contains(&Event::GameOver(Outcome::Lost)));
+        // Once the game is over, stepping does nothing.
+        assert!(sim.step(&[]).is_empty());
+    }
+
+    #[test]
+    fn running_into_itself_loses() {
+        let food = [(3, 2), (4, 2), (5, 2)];
+        let mut sim = Simulation::from_level(level(10, 6, EdgePolicy::Wrap, &food), 1);
+        for _ in 0..3 {
+            sim.step(&[]);
+        }
+        assert_eq!(sim.snake().length(), 5);
+        for &dir in [Direction::Down, Direction::Left, Direction::Up].iter() {
+            sim.step(&[dir]);
+        }
+        assert_eq!(sim.outcome(), Some(Outcome::Lost));
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::VecDeque;
+
+use crate::board::{Board, BoardView, EdgePolicy};
+use crate::food::FoodKind;
+use crate::grid::{Direction, GridPosition, Rotation};
+use crate::occupancy::OccupancyGrid;
+
+// How many turns a snake will hold on to before it starts ignoring new ones.
+// This is enough for any sensible combination of quick turns, but keeps a
+// player mashing keys from queuing up moves the snake will make long after.
+pub const MAX_QUEUED_TURNS: usize = 3;
+
+// There are two ways to steer a snake: tell it which way to face, the way the
+// arrow keys do, or tell it to turn left or right from wherever it's heading, the
+// way the snake games on old phones did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Steer {
+    Face(Direction),
+    Rotate(Rotation),
+}
+
+impl From<Direction> for Steer {
+    fn from(dir: Direction) -> Self {
+        Steer::Face(dir)
+    }
+}
+
+// This is mostly just a semantic abstraction over a `GridPosition` to represent
+// a segment of the snake. It could be useful to, say, have each segment contain its
+// own color or something similar. This is an exercise left up to the reader ;)
+#[derive(Clone, Copy, Debug)]
+pub struct Segment {
+    pub pos: GridPosition,
+}
+
+impl Segment {
+    pub fn new(pos: GridPosition) -> Self {
+        Segment { pos }
+    }
+}
+
+// Here we define an enum of the possible things that the snake could have "eaten"
+// during an update of the game. It could have either eaten a piece of `Food` of
+// some kind, it could have eaten `Itself` if the head ran into its body, or it
+// could have run headfirst into a `Wall`, either at the edge of the board or
+// an obstacle on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ate {
+    Itself,
+    Food(FoodKind),
+    Wall,
+}
+
+// Now we make a struct that contains all the information needed to describe the
+// state of the Snake itself.
+#[derive(Clone, Debug)]
+pub struct Snake {
+    // First we have the head of the snake, which is a single `Segment`.
+    head: Segment,
+    // Then we have the turns the player has asked for that the snake hasn't
+    // made yet. Each call to `update` takes at most one turn off the front, so
+    // pressing two keys in quick succession turns the snake twice instead of
+    // the second key overwriting the first.
+    turns: VecDeque<Direction>,
+    // Next we have the body, which we choose to represent as a `VecDeque`
+    // of `Segment`s. The front of the body is right behind the head.
+    body: VecDeque<Segment>,
+    // Alongside the body we keep an occupancy grid the size of the board with
+    // every cell the snake covers marked, so that asking whether the snake is in
+    // a given cell doesn't mean walking its whole body.
+    occupancy: OccupancyGrid,
+    // Now we have a property that represents the result of the last update
+    // that was performed. The snake could have eaten nothing (None), some kind of
+    // Food (Some(Ate::Food(kind))), or Itself (Some(Ate::Itself))
+    ate: Option<Ate>,
+    // Then we store the direction that the snake was traveling the last
+    // time that `update` was called, which we will use to determine valid
+    // directions that it could move the next time update is called.
+    last_update_dir: Direction,
+    // Finally we remember how the last update moved the snake, so that a frontend
+    // can slide the head and tail between cells instead of jumping a whole cell
+    // at a time. `last_move` is the way the head went, if it moved at all, and
+    // `vacated_tail` is the cell the tail left behind and the way it went.
+    last_move: Option<Direction>,
+    vacated_tail: Option<(GridPosition, Direction)>,
+}
+
+impl Snake {
+    pub fn new(pos: GridPosition, dir: Direction, board: &Board) -> Self {
+        let mut body = VecDeque::new();
+        // Our snake will initially have a head and one body segment right behind
+        // it, and will be moving in the direction `dir`.
+        let tail = Segment::new(GridPosition::new_from_move(pos, dir.inverse(), board));
+        body.push_back(tail);
+        let mut occupancy = OccupancyGrid::new(board.width, board.height);
+        occupancy.occupy(pos);
+        occupancy.occupy(tail.pos);
+        Snake {
+            head: Segment::new(pos),
+            turns: VecDeque::with_capacity(MAX_QUEUED_TURNS),
+            last_update_dir: dir,
+            body,
+            occupancy,
+            ate: None,
+            last_move: None,
+            vacated_tail: None,
+        }
+    }
+
+    // Some read-only accessors so that frontends can find out where the
+    // snake is and what it's doing without being able to mess with it.
+    pub fn head(&self) -> Segment {
+        self.head
+    }
+
+    pub fn body(&self) -> impl Iterator<Item = &Segment> {
+        self.body.iter()
+    }
+
+    // The direction the snake will move the next time `update` is called.
+    pub fn dir(&self) -> Direction {
+        self.turns.front().copied().unwrap_or(self.last_update_dir)
+    }
+
+    pub fn last_update_dir(&self) -> Direction {
+        self.last_update_dir
+    }
+
+    // The direction the snake will be heading once it's made every turn in its queue.
+    pub fn heading(&self) -> Direction {
+        self.turns.back().copied().unwrap_or(self.last_update_dir)
+    }
+
+    pub fn ate(&self) -> Option<Ate> {
+        self.ate
+    }
+
+    pub fn last_move(&self) -> Option<Direction> {
+        self.last_move
+    }
+
+    pub fn vacated_tail(&self) -> Option<(GridPosition, Direction)> {
+        self.vacated_tail
+    }
+
+    // The cells covered by the snake, for anyone who needs to ask about lots of them.
+    pub fn occupancy(&self) -> &OccupancyGrid {
+        &self.occupancy
+    }
+
+    // The length of the snake counts the head as well as every body segment.
+    pub fn length(&self) -> usize {
+        self.body.len() + 1
+    }
+
+    // Here we try to queue up a turn to face `dir`. We check the turn against the
+    // direction the snake will be heading once every turn already in the queue
+    // has been made, so that Up then Left is a perfectly good U-turn even though
+    // Left is the opposite of the way the snake is going right now. Turns that
+    // would reverse the snake onto itself or that don't change its heading are
+    // ignored, as is anything that doesn't fit in the queue.
+    pub fn turn(&mut self, dir: Direction) {
+        let heading = self.heading();
+        if dir != heading && dir.inverse() != heading && self.turns.len() < MAX_QUEUED_TURNS {
+            self.turns.push_back(dir);
+        }
+    }
+
+    // Queues up a quarter turn from the way the snake will be heading. With no
+    // turns queued, that's the way it last moved, `last_update_dir`. We don't turn
+    // from `last_update_dir` when there are turns queued, though: a second quick
+    // tap would then ask for the same turn as the first and be dropped, when the
+    // player meant to turn twice. Turning from the heading instead, two quick turns
+    // the same way make a U-turn, just like they would with `turn`.
+    pub fn rotate(&mut self, rotation: Rotation) {
+        self.turn(self.heading().rotated(rotation));
+    }
+
+    pub fn steer(&mut self, steer: Steer) {
+        match steer {
+            Steer::Face(dir) => self.turn(dir),
+            Steer::Rotate(rotation) => self.rotate(rotation),
+        }
+    }
+
+    // A helper function that tells us whether any part of the snake,
+    // head or body, is covering the given position.
+    pub fn occupies(&self, pos: GridPosition) -> bool {
+        self.occupancy.is_occupied(pos)
+    }
+
+    // On a board that bounces, a snake that runs into a wall is deflected along it
+    // instead. We try a quarter turn clockwise first and then counter-clockwise,
+    // preferring whichever way isn't blocked by the snake's own body or an obstacle.
+    // On any other board, walls don't deflect anything and we return `None`.
+    fn deflect(&self, board: &Board, dir: Direction) -> Option<(Direction, GridPosition)> {
+        if board.edges != EdgePolicy::Bounce {
+            return None;
+        }
+        let options: Vec<(Direction, GridPosition)> = [dir.clockwise(), dir.counter_clockwise()]
+            .iter()
+            .filter_map(|&turn| board.step(self.head.pos, turn).map(|pos| (turn, pos)))
+            .collect();
+        options
+            .iter()
+            .find(|&&(_, pos)| !self.occupies(pos) && !board.is_obstacle(pos))
+            .or_else(|| options.first())
+            .copied()
+    }
+
+    // Takes up to `count` segments off the end of the body, although we always
+    // leave at least one segment behind the head. We remember the last cell we
+    // gave up, which is the one right next to the new end of the tail, so that
+    // it can be drawn sliding away.
+    fn shed(&mut self, count: usize, board: &Board) {
+        for _ in 0..count.min(self.body.len().saturating_sub(1)) {
+            if let Some(tail) = self.body.pop_back() {
+                self.occupancy.vacate(tail.pos);
+                let new_tail = self.body.back().unwrap_or(&self.head).pos;
+                self.vacated_tail = board
+                    .direction_between(tail.pos, new_tail)
+                    .map(|dir| (tail.pos, dir));
+            }
+        }
+    }
+
+    // The main update function for our snake which gets called every time
+    // we want to update the game state. The view tells us about the board we're
+    // moving on and the food lying on it.
+    pub fn update(&mut self, view: &BoardView) {
+        let board = view.board;
+        // Until we know better, this update hasn't moved anything.
+        self.last_move = None;
+        self.vacated_tail = None;
+        // First we take the next queued turn, if there is one, to find out which way
+        // we're going. Then we ask the board where that move takes our head.
+        let mut dir = self.turns.pop_front().unwrap_or(self.last_update_dir);
+        let new_head_pos = match board.step(self.head.pos, dir) {
+            Some(pos) => pos,
+            // If the move would take us through a wall, we either bounce off it or,
+            // if we can't, we've hit the wall. In that case the snake stays where it
+            // is, since there's nowhere for it to go.
+            None => match self.deflect(board, dir) {
+                Some((deflected, pos)) => {
+                    dir = deflected;
+                    // Any turns the player queued up were meant for the direction
+                    // we were going before we bounced, so we throw them away.
+                    self.turns.clear();
+                    pos
+                }
+                None => {
+                    self.ate = Some(Ate::Wall);
+                    return;
+                }
+            },
+        };
+        // Obstacles don't bounce anything, so running into one is just like running
+        // into a wall on a board with solid edges.
+        if board.is_obstacle(new_head_pos) {
+            self.ate = Some(Ate::Wall);
+            return;
+        }
+        // Before we move, we look up whether the new head position is already covered
+        // by the snake. If it is, the snake is about to eat itself.
+        let eats_self = self.occupies(new_head_pos);
+        // Next we create a new segment will be our new head segment using the
+        // new position we just made.
+        let new_head = Segment::new(new_head_pos);
+        // Then we push our current head Segment onto the front of our body
+        self.body.push_front(self.head);
+        // And finally make our actual head the new Segment we created. This has
+        // effectively moved the snake in the current direction.
+        self.head = new_head;
+        self.occupancy.occupy(new_head_pos);
+        // Next we check whether the snake eats itself or some food, and if so,
+        // we set our `ate` member to reflect that state.
+        if eats_self {
+            self.ate = Some(Ate::Itself);
+        } else if let Some(food) = view.food_at(new_head_pos) {
+            self.ate = Some(Ate::Food(food.kind))
+        } else {
+            self.ate = None
+        }
+        // If we didn't eat anything this turn, we remove the last segment from our body,
+        // which gives the illusion that the snake is moving. In reality, all the segments stay
+        // stationary, we just add a segment to the front and remove one from the back. If we eat
+        // a piece of food that grows us, then we leave the last segment so that we extend our
+        // body by one, and if we eat one that shrinks us, we take even more off the end.
+        let shed = match self.ate {
+            None => 1,
+            Some(Ate::Food(kind)) => (1 - kind.growth()).max(0) as usize,
+            Some(Ate::Itself) | Some(Ate::Wall) => 0,
+        };
+        self.shed(shed, board);
+        // And set our last_update_dir to the direction we just moved.
+        self.last_update_dir = dir;
+        self.last_move = Some(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::food::{Food, FoodField};
+
+    fn step(snake: &mut Snake, board: &Board, food: &FoodField) {
+        snake.update(&BoardView::new(board, food));
+    }
+
+    #[test]
+    fn moves_and_turns() {
+        let board = Board::new(10, 10);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(2, 5), Direction::Right, &board);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(3, 5));
+        // Turning straight back onto the body is ignored.
+        snake.turn(Direction::Left);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(4, 5));
+        snake.turn(Direction::Down);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(4, 6));
+        assert_eq!(snake.length(), 2);
+    }
+
+    #[test]
+    fn two_quick_turns_make_a_u_turn() {
+        let board = Board::new(10, 10);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(5, 5), Direction::Right, &board);
+        // Left is straight back onto the body right now, but not once the snake
+        // has turned up, so both turns are kept and made one update at a time.
+        snake.turn(Direction::Up);
+        snake.turn(Direction::Left);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(5, 4));
+        assert_eq!(snake.dir(), Direction::Left);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(4, 4));
+        assert_eq!(snake.ate(), None);
+        assert_eq!(snake.last_update_dir(), Direction::Left);
+    }
+
+    #[test]
+    fn rotates_from_the_way_it_last_moved() {
+        let board = Board::new(10, 10);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(5, 5), Direction::Right, &board);
+        snake.rotate(Rotation::Clockwise);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(5, 6));
+        snake.rotate(Rotation::CounterClockwise);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(6, 6));
+        assert_eq!(snake.last_update_dir(), Direction::Right);
+    }
+
+    #[test]
+    fn quick_rotations_turn_from_the_last_one_queued() {
+        let board = Board::new(10, 10);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(5, 5), Direction::Right, &board);
+        // Two quick turns the same way make a U-turn, rather than the second being
+        // dropped for asking for the same turn as the first.
+        snake.rotate(Rotation::CounterClockwise);
+        snake.rotate(Rotation::CounterClockwise);
+        assert_eq!(snake.last_update_dir(), Direction::Right);
+        assert_eq!(snake.heading(), Direction::Left);
+        step(&mut snake, &board, &food);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(4, 4));
+        // And a turn one way then the other makes a zigzag.
+        snake.rotate(Rotation::CounterClockwise);
+        snake.rotate(Rotation::Clockwise);
+        step(&mut snake, &board, &food);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(3, 5));
+        assert_eq!(snake.last_update_dir(), Direction::Left);
+        assert_eq!(snake.ate(), None);
+    }
+
+    #[test]
+    fn turns_past_the_end_of_the_queue_are_dropped() {
+        let board = Board::new(20, 20);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(10, 10), Direction::Right, &board);
+        // Every turn is a quarter turn from the one before, so each would be kept
+        // if there were room for it.
+        let turns: Vec<Direction> = (0..MAX_QUEUED_TURNS + 2)
+            .map(|i| {
+                [
+                    Direction::Up,
+                    Direction::Left,
+                    Direction::Down,
+                    Direction::Right,
+                ][i % 4]
+            })
+            .collect();
+        for &dir in turns.iter() {
+            snake.turn(dir);
+        }
+        assert_eq!(snake.heading(), turns[MAX_QUEUED_TURNS - 1]);
+        for &dir in turns[..MAX_QUEUED_TURNS].iter() {
+            step(&mut snake, &board, &food);
+            assert_eq!(snake.last_update_dir(), dir);
+        }
+        // The queue is empty now, so the snake carries straight on.
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.last_update_dir(), turns[MAX_QUEUED_TURNS - 1]);
+        assert_eq!(snake.ate(), None);
+    }
+
+    #[test]
+    fn eating_food_grows_the_snake() {
+        let board = Board::new(10, 10);
+        let mut food = FoodField::new();
+        food.insert(Food::new(GridPosition::new(3, 5)));
+        let mut snake = Snake::new(GridPosition::new(2, 5), Direction::Right, &board);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.ate(), Some(Ate::Food(FoodKind::Normal)));
+        assert_eq!(snake.length(), 3);
+    }
+
+    #[test]
+    fn running_into_a_solid_edge_hits_the_wall() {
+        let board = Board::new(4, 4).with_edges(EdgePolicy::Solid);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(3, 1), Direction::Right, &board);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.ate(), Some(Ate::Wall));
+        assert_eq!(snake.head().pos, GridPosition::new(3, 1));
+    }
+
+    #[test]
+    fn bounces_along_a_wall() {
+        let board = Board::new(6, 6).with_edges(EdgePolicy::Bounce);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(5, 2), Direction::Right, &board);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.ate(), None);
+        // Both ways along the wall are clear, so the snake turns clockwise.
+        assert_eq!(snake.head().pos, GridPosition::new(5, 3));
+        assert_eq!(snake.last_update_dir(), Direction::Down);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.head().pos, GridPosition::new(5, 4));
+    }
+
+    #[test]
+    fn bounces_out_of_a_corner_the_only_way_it_can() {
+        let board = Board::new(6, 6).with_edges(EdgePolicy::Bounce);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(5, 5), Direction::Right, &board);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.ate(), None);
+        assert_eq!(snake.head().pos, GridPosition::new(5, 4));
+        assert_eq!(snake.last_update_dir(), Direction::Up);
+    }
+
+    #[test]
+    fn bounces_away_from_its_own_body() {
+        let board = Board::new(6, 6).with_edges(EdgePolicy::Bounce);
+        let mut food = FoodField::new();
+        food.insert(Food::new(GridPosition::new(4, 3)));
+        food.insert(Food::new(GridPosition::new(5, 3)));
+        // The snake curls round from (5, 4) up to (5, 3), next to the wall, so its
+        // own tail is right below its head.
+        let mut snake = Snake::new(GridPosition::new(4, 4), Direction::Left, &board);
+        snake.turn(Direction::Up);
+        step(&mut snake, &board, &food);
+        snake.turn(Direction::Right);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.length(), 4);
+        // Turning clockwise would run into the tail, so it goes the other way.
+        step(&mut snake, &board, &FoodField::new());
+        assert_eq!(snake.ate(), None);
+        assert_eq!(snake.head().pos, GridPosition::new(5, 2));
+        assert_eq!(snake.last_update_dir(), Direction::Up);
+    }
+
+    #[test]
+    fn hits_the_wall_when_there_is_nowhere_to_bounce() {
+        let board = Board::new(1, 6).with_edges(EdgePolicy::Bounce);
+        let food = FoodField::new();
+        let mut snake = Snake::new(GridPosition::new(0, 0), Direction::Up, &board);
+        step(&mut snake, &board, &food);
+        assert_eq!(snake.ate(), Some(Ate::Wall));
+        assert_eq!(snake.head().pos, GridPosition::new(0, 0));
+    }
+
+    #[test]
+    fn running_into_the_body_eats_itself() {
+        let board = Board::new(10, 10);
+        let mut food = FoodField::new();
+        for x in 3..6 {
+            food.insert(Food::new(GridPosition::new(x, 5)));
+        }
+        let mut snake = Snake::new(GridPosition::new(2, 5), Direction::Right, &board);
+        for _ in 0..3 {
+            step(&mut snake, &board, &food);
+        }
+        assert_eq!(snake.length(), 5);
+        let food = FoodField::new();
+        for dir in [Direction::Down, Direction::Left, Direction::Up].iter() {
+            snake.turn(*dir);
+            step(&mut snake, &board, &food);
+        }
+        assert_eq!(snake.ate(), Some(Ate::Itself));
+    }
+}
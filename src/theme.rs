@@ -0,0 +1,436 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::food::FoodKind;
+
+// A color as red, green, blue and alpha, each from 0.0 to 1.0.
+pub type Color = [f32; 4];
+
+// Mixes two colors, going from all `a` when `t` is 0.0 to all `b` when it's 1.0.
+pub fn mix(a: Color, b: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let mut mixed = a;
+    for (channel, (&from, &to)) in mixed.iter_mut().zip(a.iter().zip(b.iter())) {
+        *channel = from + (to - from) * t;
+    }
+    mixed
+}
+
+// A `Theme` is every color the game is drawn with. Themes can be read from a TOML
+// file, where colors are written as `"#rrggbb"`, `"#rrggbbaa"` or the short `"#rgb"`, and anything left
+// out comes from the classic theme.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    // What the theme is called when we tell the player which one they're using.
+    pub name: String,
+    #[serde(with = "hex_color")]
+    pub background: Color,
+    // Lines between the cells of the board. A fully transparent color turns them off.
+    #[serde(with = "hex_color")]
+    pub grid_lines: Color,
+    #[serde(with = "hex_color")]
+    pub walls: Color,
+    #[serde(with = "hex_color")]
+    pub portals: Color,
+    #[serde(with = "hex_color")]
+    pub head: Color,
+    // The body fades from the first color right behind the head to the second at the tail.
+    #[serde(with = "hex_color")]
+    pub body_start: Color,
+    #[serde(with = "hex_color")]
+    pub body_end: Color,
+    // The second player's snake, in a game for two.
+    pub rival: SnakeColors,
+    // Every kind of food has its own color.
+    pub food: FoodColors,
+    // The score line and any messages written over the board.
+    #[serde(with = "hex_color")]
+    pub hud: Color,
+}
+
+impl Theme {
+    // Reads a theme from TOML.
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    // The colors of a player's snake. The first player's snake uses the theme's own
+    // snake colors, and everyone else's the rival's.
+    pub fn snake(&self, player: usize) -> SnakeColors {
+        match player {
+            0 => SnakeColors {
+                head: self.head,
+                body_start: self.body_start,
+                body_end: self.body_end,
+            },
+            _ => self.rival,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        BuiltinTheme::default().theme()
+    }
+}
+
+// The colors a snake is drawn in. In a theme file, the rival's go in their own
+// `[rival]` table, and any that are left out come from the classic theme.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnakeColors {
+    #[serde(with = "hex_color")]
+    pub head: Color,
+    // The body fades from the first color right behind the head to the second at the tail.
+    #[serde(with = "hex_color")]
+    pub body_start: Color,
+    #[serde(with = "hex_color")]
+    pub body_end: Color,
+}
+
+impl SnakeColors {
+    // The color of a body segment, counting from 0 right behind the head, on a
+    // body that's `length` segments long.
+    pub fn body_color(&self, segment: usize, length: usize) -> Color {
+        let t = segment as f32 / length.saturating_sub(1).max(1) as f32;
+        mix(self.body_start, self.body_end, t)
+    }
+}
+
+impl Default for SnakeColors {
+    fn default() -> Self {
+        BuiltinTheme::default().theme().rival
+    }
+}
+
+// The colors of each kind of food. In a theme file these go in their own `[food]`
+// table, and any that are left out come from the classic theme.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoodColors {
+    #[serde(with = "hex_color")]
+    pub normal: Color,
+    #[serde(with = "hex_color")]
+    pub bonus: Color,
+    #[serde(with = "hex_color")]
+    pub shrink: Color,
+    #[serde(with = "hex_color")]
+    pub speed_up: Color,
+    #[serde(with = "hex_color")]
+    pub slow_down: Color,
+    #[serde(with = "hex_color")]
+    pub poison: Color,
+}
+
+impl FoodColors {
+    pub fn color(&self, kind: FoodKind) -> Color {
+        match kind {
+            FoodKind::Normal => self.normal,
+            FoodKind::Bonus => self.bonus,
+            FoodKind::Shrink => self.shrink,
+            FoodKind::SpeedUp => self.speed_up,
+            FoodKind::SlowDown => self.slow_down,
+            FoodKind::Poison => self.poison,
+        }
+    }
+}
+
+impl Default for FoodColors {
+    fn default() -> Self {
+        BuiltinTheme::default().theme().food
+    }
+}
+
+// The themes that come with the game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuiltinTheme {
+    #[default]
+    Classic,
+    Dark,
+    HighContrast,
+    Deuteranopia,
+    Protanopia,
+}
+
+impl BuiltinTheme {
+    pub const ALL: [BuiltinTheme; 5] = [
+        BuiltinTheme::Classic,
+        BuiltinTheme::Dark,
+        BuiltinTheme::HighContrast,
+        BuiltinTheme::Deuteranopia,
+        BuiltinTheme::Protanopia,
+    ];
+
+    // The classic theme is the bright green board the game has always had. The
+    // deuteranopia and protanopia themes are built from the Okabe-Ito palette, and
+    // keep the snake and the food apart by brightness and by blue against orange
+    // or yellow rather than by red against green.
+    // Their rival snake is left in grays, since the palette's other colors all
+    // belong to the food.
+    pub fn theme(&self) -> Theme {
+        let name = self.to_string();
+        match *self {
+            BuiltinTheme::Classic => Theme {
+                name,
+                background: [0.0, 1.0, 0.0, 1.0],
+                grid_lines: [0.0, 0.0, 0.0, 0.0],
+                walls: [0.2, 0.2, 0.2, 1.0],
+                portals: [0.0, 0.6, 0.6, 1.0],
+                head: [1.0, 0.0, 0.0, 1.0],
+                body_start: [1.0, 0.5, 0.0, 1.0],
+                body_end: [1.0, 0.5, 0.0, 1.0],
+                rival: SnakeColors {
+                    head: [0.9, 0.0, 0.5, 1.0],
+                    body_start: [1.0, 0.6, 0.8, 1.0],
+                    body_end: [1.0, 0.6, 0.8, 1.0],
+                },
+                food: FoodColors {
+                    normal: [0.0, 0.0, 1.0, 1.0],
+                    bonus: [1.0, 0.85, 0.0, 1.0],
+                    shrink: [0.6, 0.2, 0.8, 1.0],
+                    speed_up: [1.0, 1.0, 1.0, 1.0],
+                    slow_down: [0.5, 0.3, 0.1, 1.0],
+                    poison: [0.1, 0.1, 0.1, 1.0],
+                },
+                hud: [0.0, 0.0, 0.0, 1.0],
+            },
+            BuiltinTheme::Dark => Theme {
+                name,
+                background: [0.08, 0.08, 0.1, 1.0],
+                grid_lines: [1.0, 1.0, 1.0, 0.05],
+                walls: [0.45, 0.45, 0.5, 1.0],
+                portals: [0.35, 0.55, 1.0, 1.0],
+                head: [0.55, 0.9, 0.45, 1.0],
+                body_start: [0.35, 0.75, 0.3, 1.0],
+                body_end: [0.15, 0.4, 0.15, 1.0],
+                rival: SnakeColors {
+                    head: [0.95, 0.6, 0.3, 1.0],
+                    body_start: [0.85, 0.5, 0.25, 1.0],
+                    body_end: [0.45, 0.25, 0.1, 1.0],
+                },
+                food: FoodColors {
+                    normal: [0.95, 0.35, 0.35, 1.0],
+                    bonus: [1.0, 0.85, 0.2, 1.0],
+                    shrink: [0.7, 0.5, 0.95, 1.0],
+                    speed_up: [0.3, 0.85, 0.95, 1.0],
+                    slow_down: [0.6, 0.45, 0.3, 1.0],
+                    poison: [0.55, 0.2, 0.55, 1.0],
+                },
+                hud: [0.85, 0.85, 0.85, 1.0],
+            },
+            BuiltinTheme::HighContrast => Theme {
+                name,
+                background: [0.0, 0.0, 0.0, 1.0],
+                grid_lines: [0.0, 0.0, 0.0, 0.0],
+                walls: [1.0, 1.0, 1.0, 1.0],
+                portals: [0.3, 0.3, 1.0, 1.0],
+                head: [1.0, 1.0, 0.0, 1.0],
+                body_start: [1.0, 1.0, 1.0, 1.0],
+                body_end: [1.0, 1.0, 1.0, 1.0],
+                rival: SnakeColors {
+                    head: [0.6, 0.8, 1.0, 1.0],
+                    body_start: [0.3, 0.6, 1.0, 1.0],
+                    body_end: [0.3, 0.6, 1.0, 1.0],
+                },
+                food: FoodColors {
+                    normal: [0.0, 1.0, 1.0, 1.0],
+                    bonus: [1.0, 0.0, 1.0, 1.0],
+                    shrink: [1.0, 0.5, 0.0, 1.0],
+                    speed_up: [0.0, 1.0, 0.0, 1.0],
+                    slow_down: [0.6, 0.6, 1.0, 1.0],
+                    poison: [1.0, 0.0, 0.0, 1.0],
+                },
+                hud: [1.0, 1.0, 1.0, 1.0],
+            },
+            BuiltinTheme::Deuteranopia => Theme {
+                name,
+                background: [0.12, 0.12, 0.12, 1.0],
+                grid_lines: [1.0, 1.0, 1.0, 0.06],
+                walls: [0.6, 0.6, 0.6, 1.0],
+                portals: [1.0, 1.0, 1.0, 0.5],
+                head: [0.0, 0.447, 0.698, 1.0],
+                body_start: [0.337, 0.706, 0.914, 1.0],
+                body_end: [0.0, 0.447, 0.698, 1.0],
+                rival: SnakeColors {
+                    head: [1.0, 1.0, 1.0, 1.0],
+                    body_start: [0.7, 0.7, 0.7, 1.0],
+                    body_end: [0.4, 0.4, 0.4, 1.0],
+                },
+                food: FoodColors {
+                    normal: [0.902, 0.624, 0.0, 1.0],
+                    bonus: [0.941, 0.894, 0.259, 1.0],
+                    shrink: [0.8, 0.475, 0.655, 1.0],
+                    speed_up: [1.0, 1.0, 1.0, 1.0],
+                    slow_down: [0.0, 0.62, 0.451, 1.0],
+                    poison: [0.835, 0.369, 0.0, 1.0],
+                },
+                hud: [0.95, 0.95, 0.95, 1.0],
+            },
+            BuiltinTheme::Protanopia => Theme {
+                name,
+                background: [0.12, 0.12, 0.12, 1.0],
+                grid_lines: [1.0, 1.0, 1.0, 0.06],
+                walls: [0.6, 0.6, 0.6, 1.0],
+                portals: [1.0, 1.0, 1.0, 0.5],
+                head: [0.941, 0.894, 0.259, 1.0],
+                body_start: [0.337, 0.706, 0.914, 1.0],
+                body_end: [0.0, 0.447, 0.698, 1.0],
+                rival: SnakeColors {
+                    head: [1.0, 1.0, 1.0, 1.0],
+                    body_start: [0.7, 0.7, 0.7, 1.0],
+                    body_end: [0.4, 0.4, 0.4, 1.0],
+                },
+                food: FoodColors {
+                    normal: [0.8, 0.475, 0.655, 1.0],
+                    bonus: [0.902, 0.624, 0.0, 1.0],
+                    shrink: [0.0, 0.62, 0.451, 1.0],
+                    speed_up: [1.0, 1.0, 1.0, 1.0],
+                    slow_down: [0.5, 0.5, 0.5, 1.0],
+                    poison: [0.835, 0.369, 0.0, 1.0],
+                },
+                hud: [0.95, 0.95, 0.95, 1.0],
+            },
+        }
+    }
+}
+
+impl fmt::Display for BuiltinTheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BuiltinTheme::Classic => "classic",
+            BuiltinTheme::Dark => "dark",
+            BuiltinTheme::HighContrast => "high-contrast",
+            BuiltinTheme::Deuteranopia => "deuteranopia",
+            BuiltinTheme::Protanopia => "protanopia",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for BuiltinTheme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        BuiltinTheme::ALL
+            .iter()
+            .copied()
+            .find(|theme| theme.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown theme {:?}; expected classic, dark, high-contrast, deuteranopia or protanopia",
+                    name
+                )
+            })
+    }
+}
+
+// The (de)serializers we use to write colors as hex strings.
+mod hex_color {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Color;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let text = if a == 255 {
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        };
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse(&text).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid color {:?}; expected \"#rrggbb\", \"#rrggbbaa\" or \"#rgb\"",
+                text
+            ))
+        })
+    }
+
+    // Every channel is two hex digits, or in the short form one, which stands
+    // for that digit twice, so `#f80` is `#ff8800`.
+    pub(super) fn parse(text: &str) -> Option<Color> {
+        let hex = text.strip_prefix('#')?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits = match hex.len() {
+            3 => 1,
+            6 | 8 => 2,
+            _ => return None,
+        };
+        let mut color = [1.0; 4];
+        for (i, channel) in color.iter_mut().enumerate().take(hex.len() / digits) {
+            let byte = u8::from_str_radix(&hex[i * digits..(i + 1) * digits], 16).ok()?;
+            let byte = if digits == 1 { byte * 17 } else { byte };
+            *channel = byte as f32 / 255.0;
+        }
+        Some(color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_hex_colors() {
+        assert_eq!(hex_color::parse("#ff0000"), Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(
+            hex_color::parse("#00FF0080"),
+            Some([0.0, 1.0, 0.0, 128.0 / 255.0])
+        );
+        assert_eq!(hex_color::parse("#f80"), hex_color::parse("#ff8800"));
+        assert_eq!(hex_color::parse("#000"), Some([0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn refuses_anything_else() {
+        for text in [
+            "ff0000", "#ff00", "#ff00000", "#gg0000", "#+f0000", "#ff 000", "#ﬀ00", "",
+        ]
+        .iter()
+        {
+            assert_eq!(hex_color::parse(text), None, "{:?}", text);
+        }
+        assert!(Theme::from_toml("head = \"red\"").is_err());
+        assert!(Theme::from_toml("head = 12").is_err());
+    }
+
+    // TOML wants plain values ahead of tables, so we go through a `toml::Value`,
+    // which sorts that out, rather than writing the fields in the order they're in.
+    fn to_toml(theme: &Theme) -> String {
+        toml::Value::try_from(theme).unwrap().to_string()
+    }
+
+    #[test]
+    fn writes_colors_the_way_it_reads_them() {
+        let theme = Theme {
+            head: [1.0, 0.5, 0.0, 1.0],
+            grid_lines: [1.0, 1.0, 1.0, 0.05],
+            ..Theme::default()
+        };
+        let text = to_toml(&theme);
+        assert!(text.contains("head = \"#ff8000\""), "{}", text);
+        // A color that isn't fully opaque keeps its alpha.
+        assert!(text.contains("grid_lines = \"#ffffff0d\""), "{}", text);
+        // Writing what we read back gives the same file again.
+        let read = Theme::from_toml(&text).unwrap();
+        assert_eq!(to_toml(&read), text);
+    }
+
+    #[test]
+    fn fills_in_anything_left_out_from_the_classic_theme() {
+        let theme = Theme::from_toml("name = \"mine\"\nhead = \"#123\"\n").unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.head, hex_color::parse("#112233").unwrap());
+        assert_eq!(theme.walls, BuiltinTheme::Classic.theme().walls);
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+// If the game falls this far behind, say because the window was dragged around or
+// the machine went to sleep, we give up on catching up and just carry on from now.
+// Otherwise a long stall would be followed by the snake racing across the board.
+pub const MAX_CATCH_UP_TICKS: u32 = 5;
+
+// A `FixedTimestep` turns the uneven time between frames into an exact number of
+// simulation ticks. Every frame, the frontend tells it how much time has passed,
+// and it hands out one tick for every whole interval that has built up. Whatever
+// is left over carries on to the next frame, so no time is lost to rounding and
+// the number of ticks over a minute is the same however fast the frames come.
+//
+// It never looks at a clock itself. The `ggez` frontend feeds it
+// `ggez::timer::delta`, and anything else can feed it whatever durations it likes,
+// which makes it easy to drive time by hand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedTimestep {
+    // Time that has passed but hasn't been turned into ticks yet.
+    accumulator: Duration,
+    // How many ticks we've handed out since the last call to `advance`.
+    ticks_this_frame: u32,
+    max_catch_up_ticks: u32,
+}
+
+impl FixedTimestep {
+    pub fn new() -> Self {
+        FixedTimestep {
+            accumulator: Duration::ZERO,
+            ticks_this_frame: 0,
+            max_catch_up_ticks: MAX_CATCH_UP_TICKS,
+        }
+    }
+
+    // Changes how many ticks a single frame may run before the rest is dropped.
+    pub fn with_max_catch_up_ticks(mut self, ticks: u32) -> Self {
+        self.max_catch_up_ticks = ticks.max(1);
+        self
+    }
+
+    // How much time is waiting to be turned into ticks.
+    pub fn accumulated(&self) -> Duration {
+        self.accumulator
+    }
+
+    // Adds the time that has passed since the last frame.
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.accumulator += elapsed;
+        self.ticks_this_frame = 0;
+    }
+
+    // Takes one tick's worth of time out of the accumulator, if there is enough.
+    // This is meant to be called in a loop, since after a slow frame there may be
+    // several ticks to catch up on. The interval is passed in every time because
+    // it can change between ticks as the game speeds up.
+    pub fn tick(&mut self, interval: Duration) -> bool {
+        if interval.is_zero() || self.accumulator < interval {
+            return false;
+        }
+        if self.ticks_this_frame >= self.max_catch_up_ticks {
+            // We've fallen too far behind, so we throw away every whole interval
+            // that's left and keep only the part of a tick we're partway through.
+            self.accumulator =
+                Duration::from_nanos((self.accumulator.as_nanos() % interval.as_nanos()) as u64);
+            return false;
+        }
+        self.accumulator -= interval;
+        self.ticks_this_frame += 1;
+        true
+    }
+
+    // How far we are through the next tick, from 0.0 just after a tick to nearly
+    // 1.0 just before the next one.
+    pub fn alpha(&self, interval: Duration) -> f32 {
+        if interval.is_zero() {
+            return 0.0;
+        }
+        (self.accumulator.as_secs_f32() / interval.as_secs_f32()).min(1.0)
+    }
+
+    // Forgets any time that has built up, for when a new game starts.
+    pub fn reset(&mut self) {
+        self.accumulator = Duration::ZERO;
+        self.ticks_this_frame = 0;
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        FixedTimestep::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    // Advances by `elapsed` and runs every tick that's due, the way the frontend
+    // does each frame.
+    fn frame(timestep: &mut FixedTimestep, elapsed: Duration) -> u32 {
+        timestep.advance(elapsed);
+        let mut ticks = 0;
+        while timestep.tick(INTERVAL) {
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn hands_out_a_tick_for_every_whole_interval() {
+        let mut timestep = FixedTimestep::new();
+        assert_eq!(frame(&mut timestep, Duration::from_millis(50)), 0);
+        assert_eq!(frame(&mut timestep, Duration::from_millis(50)), 1);
+        assert_eq!(frame(&mut timestep, Duration::from_millis(250)), 2);
+        assert_eq!(timestep.accumulated(), Duration::from_millis(50));
+        assert_eq!(timestep.alpha(INTERVAL), 0.5);
+    }
+
+    #[test]
+    fn uneven_frames_add_up_to_the_same_ticks() {
+        let mut timestep = FixedTimestep::new();
+        let frames = [17, 33, 16, 50, 34, 16, 17, 100, 17];
+        let ticks: u32 = frames
+            .iter()
+            .map(|&ms| frame(&mut timestep, Duration::from_millis(ms)))
+            .sum();
+        assert_eq!(ticks, 3);
+        assert_eq!(timestep.accumulated(), Duration::from_millis(0));
+    }
+
+    #[test]
+    fn a_long_stall_only_catches_up_so_far() {
+        let mut timestep = FixedTimestep::new();
+        assert_eq!(
+            frame(&mut timestep, Duration::from_millis(10_050)),
+            MAX_CATCH_UP_TICKS
+        );
+        // The rest of the stall is dropped, apart from the part of a tick we
+        // were partway through.
+        assert_eq!(timestep.accumulated(), Duration::from_millis(50));
+        assert_eq!(frame(&mut timestep, Duration::from_millis(50)), 1);
+    }
+
+    #[test]
+    fn the_catch_up_limit_can_be_changed() {
+        let mut timestep = FixedTimestep::new().with_max_catch_up_ticks(2);
+        assert_eq!(frame(&mut timestep, Duration::from_millis(1_000)), 2);
+        assert_eq!(timestep.accumulated(), Duration::ZERO);
+
+        let mut timestep = FixedTimestep::new().with_max_catch_up_ticks(0);
+        assert_eq!(frame(&mut timestep, Duration::from_millis(300)), 1);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::simulation::Outcome;
+
+// How many players can play on one keyboard at once.
+pub const MAX_PLAYERS: usize = 2;
+
+// A `VersusMatch` keeps the tally of a match between several players, made up of
+// rounds of snake. Whoever wins `rounds_to_win` rounds first wins the match. A
+// round nobody wins counts as a draw and doesn't bring anyone closer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersusMatch {
+    rounds_to_win: u32,
+    wins: Vec<u32>,
+    draws: u32,
+}
+
+impl VersusMatch {
+    pub fn new(players: usize, rounds_to_win: u32) -> Self {
+        VersusMatch {
+            rounds_to_win: rounds_to_win.max(1),
+            wins: vec![0; players],
+            draws: 0,
+        }
+    }
+
+    pub fn rounds_to_win(&self) -> u32 {
+        self.rounds_to_win
+    }
+
+    // How many rounds each player has won, in player order.
+    pub fn wins(&self) -> &[u32] {
+        &self.wins
+    }
+
+    pub fn draws(&self) -> u32 {
+        self.draws
+    }
+
+    pub fn rounds_played(&self) -> u32 {
+        self.wins.iter().sum::<u32>() + self.draws
+    }
+
+    // Counts a finished round. Once the match is over, we don't count any more.
+    // A round against other snakes ends in a winner or a draw, so a round won or
+    // lost on our own, or won by somebody who isn't in the match, is refused.
+    pub fn record(&mut self, outcome: Outcome) -> Result<(), String> {
+        if self.is_over() {
+            return Ok(());
+        }
+        match outcome {
+            Outcome::Winner(player) => match self.wins.get_mut(player) {
+                Some(wins) => *wins += 1,
+                None => {
+                    return Err(format!(
+                        "player {} won the round, but only {} are playing",
+                        player + 1,
+                        self.wins.len()
+                    ))
+                }
+            },
+            Outcome::Draw => self.draws += 1,
+            Outcome::Won | Outcome::Lost => {
+                return Err(format!(
+                    "a round of a match ends with a winner or a draw, not {:?}",
+                    outcome
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    // The player who won the match, once someone has.
+    pub fn winner(&self) -> Option<usize> {
+        self.wins
+            .iter()
+            .position(|&wins| wins >= self.rounds_to_win)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_to_enough_rounds_wins_the_match() {
+        let mut versus = VersusMatch::new(2, 2);
+        versus.record(Outcome::Winner(1)).unwrap();
+        versus.record(Outcome::Draw).unwrap();
+        versus.record(Outcome::Winner(0)).unwrap();
+        assert_eq!(versus.winner(), None);
+        versus.record(Outcome::Winner(1)).unwrap();
+        assert_eq!(versus.winner(), Some(1));
+        assert_eq!(versus.wins(), &[1, 2]);
+        assert_eq!(versus.draws(), 1);
+        assert_eq!(versus.rounds_played(), 4);
+        // Once the match is over, nothing more counts.
+        versus.record(Outcome::Winner(0)).unwrap();
+        assert_eq!(versus.rounds_played(), 4);
+    }
+
+    #[test]
+    fn refuses_rounds_that_arent_between_the_players() {
+        let mut versus = VersusMatch::new(2, 3);
+        assert!(versus.record(Outcome::Won).is_err());
+        assert!(versus.record(Outcome::Lost).is_err());
+        assert!(versus.record(Outcome::Winner(2)).is_err());
+        assert_eq!(versus.rounds_played(), 0);
+    }
+}